@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionIndex, SymbolKind};
+
+pub const DEFAULT_TOP: usize = 20;
+
+// Where the bytes of an executable go: sections, crates and the largest functions
+pub struct SizeBreakdown {
+    pub format: String,
+    pub file_size: u64,
+    pub text_size: u64,
+    pub sections: Vec<(String, u64)>,
+    pub crates: Vec<(String, u64)>,
+    pub symbols: Vec<(String, u64)>,
+}
+
+struct Symbol {
+    name: String,
+    address: u64,
+    size: u64,
+    section: SectionIndex,
+}
+
+pub fn analyze(path: &Path) -> Result<SizeBreakdown, Box<dyn Error>> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let file = object::File::parse(&*data)
+        .map_err(|e| format!("Cannot analyze {}: {e}", path.display()))?;
+
+    // Bytes in the file; `.bss` and friends only take up memory
+    let mut sections: Vec<(String, u64)> = file
+        .sections()
+        .filter_map(|s| {
+            let (_, size) = s.file_range()?;
+            (size > 0).then(|| (s.name().unwrap_or("<unnamed>").to_string(), size))
+        })
+        .collect();
+    sections.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    let text_size = file
+        .sections()
+        .filter(|s| s.kind() == object::SectionKind::Text)
+        .map(|s| s.size())
+        .sum();
+
+    let symbols = function_symbols(&file);
+    let mut by_crate: HashMap<String, u64> = HashMap::new();
+    let mut by_symbol: HashMap<String, u64> = HashMap::new();
+    for symbol in symbols {
+        let demangled = format!("{:#}", rustc_demangle::demangle(&symbol.name));
+        *by_crate
+            .entry(crate_name(&symbol.name, &demangled))
+            .or_default() += symbol.size;
+        *by_symbol.entry(demangled).or_default() += symbol.size;
+    }
+
+    Ok(SizeBreakdown {
+        format: format!("{:?}", file.format()),
+        file_size: data.len() as u64,
+        text_size,
+        sections,
+        crates: sorted_by_size(by_crate),
+        symbols: sorted_by_size(by_symbol),
+    })
+}
+
+// Function symbols with their sizes; Mach-O and COFF do not record sizes, so
+// those are derived from the distance to the next symbol in the same section
+fn function_symbols(file: &object::File) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = file
+        .symbols()
+        .filter(|s| s.kind() == SymbolKind::Text && s.is_definition())
+        .filter_map(|s| {
+            Some(Symbol {
+                name: s.name().ok()?.to_string(),
+                address: s.address(),
+                size: s.size(),
+                section: s.section_index()?,
+            })
+        })
+        .collect();
+    symbols.sort_by_key(|s| (s.section.0, s.address));
+    symbols.dedup_by_key(|s| (s.section.0, s.address));
+
+    for i in 0..symbols.len() {
+        if symbols[i].size > 0 {
+            continue;
+        }
+        let end = match symbols.get(i + 1) {
+            Some(next) if next.section == symbols[i].section => next.address,
+            _ => file
+                .section_by_index(symbols[i].section)
+                .map(|s| s.address() + s.size())
+                .unwrap_or(symbols[i].address),
+        };
+        symbols[i].size = end.saturating_sub(symbols[i].address);
+    }
+    symbols
+}
+
+const PRIMITIVES: [&str; 17] = [
+    "bool", "char", "str", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16",
+    "u32", "u64", "u128", "usize",
+];
+
+// First path segment of a Rust symbol. `<T as Trait>::f` counts towards T's
+// crate, or the trait's when T is not a path (`<[u8] as Debug>`, generics)
+fn crate_name(mangled: &str, demangled: &str) -> String {
+    let is_rust = mangled.starts_with("_ZN")
+        || mangled.starts_with("__ZN")
+        || mangled.starts_with("_R")
+        || mangled.starts_with("__R");
+    if !is_rust || demangled == mangled {
+        return "[non-Rust]".to_string();
+    }
+
+    let mut path = strip_type_prefix(demangled);
+    if let Some((ty, tr)) = path.split_once(" as ")
+        && !ty.contains("::")
+    {
+        path = strip_type_prefix(tr);
+    }
+    let krate = path
+        .split("::")
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('>');
+    if PRIMITIVES.contains(&krate) {
+        "core".to_string()
+    } else if krate.is_empty() || krate.contains([' ', '<', '>', '[', '(']) {
+        "[unknown]".to_string()
+    } else {
+        krate.to_string()
+    }
+}
+
+fn strip_type_prefix(mut path: &str) -> &str {
+    loop {
+        let trimmed = path.trim_start_matches(['<', '&', '*']);
+        match ["mut ", "dyn ", "const "]
+            .iter()
+            .find_map(|prefix| trimmed.strip_prefix(prefix))
+        {
+            Some(rest) => path = rest,
+            None => return trimmed,
+        }
+    }
+}
+
+fn sorted_by_size(map: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut entries: Vec<(String, u64)> = map.into_iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
+
+impl SizeBreakdown {
+    pub fn print(&self, top: usize) {
+        println!(
+            "{} file, {:.1} KB, .text {:.1} KB",
+            self.format,
+            self.file_size as f64 / 1024.0,
+            self.text_size as f64 / 1024.0
+        );
+
+        println!("\n{:>7} {:>12}  Section", "File", "Size");
+        for (name, size) in self.sections.iter().take(top) {
+            println!("{} {name}", self.columns(*size));
+        }
+
+        if self.symbols.is_empty() {
+            println!(
+                "\nNo symbol table: the binary is stripped. Use `--preset debuggable-release` or set `strip = false` in the profile to see crates and functions"
+            );
+            return;
+        }
+
+        println!("\n{:>7} {:>12}  Crate", "File", "Size");
+        for (name, size) in self.crates.iter().take(top) {
+            println!("{} {name}", self.columns(*size));
+        }
+
+        println!("\n{:>7} {:>12}  Function", "File", "Size");
+        for (name, size) in self.symbols.iter().take(top) {
+            println!("{} {name}", self.columns(*size));
+        }
+    }
+
+    fn columns(&self, size: u64) -> String {
+        format!(
+            "{:>6.1}% {:>9.1} KB ",
+            size as f64 * 100.0 / self.file_size.max(1) as f64,
+            size as f64 / 1024.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crate_of(mangled: &str) -> String {
+        crate_name(mangled, &format!("{:#}", rustc_demangle::demangle(mangled)))
+    }
+
+    #[test]
+    fn symbols_are_attributed_to_crates() {
+        assert_eq!(
+            crate_of("_ZN3std2io5stdio6_print17h1e2d3c4b5a697887E"),
+            "std"
+        );
+        assert_eq!(
+            crate_of(
+                "_ZN60_$LT$alloc..string..String$u20$as$u20$core..fmt..Display$GT$3fmt17h0123456789abcdefE"
+            ),
+            "alloc"
+        );
+        assert_eq!(
+            crate_of("_ZN45_$LT$char$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"),
+            "core"
+        );
+        assert_eq!(crate_of("memcpy"), "[non-Rust]");
+    }
+}
@@ -0,0 +1,168 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::hardening::Hardening;
+use crate::workspace::WorkspaceMember;
+
+const LIBRARY_KINDS: [&str; 3] = ["cdylib", "staticlib", "dylib"];
+const LIBRARY_EXTENSIONS: [&str; 5] = ["so", "dylib", "dll", "a", "lib"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    Executable, // 可执行文件 (bin/example)
+    Library,    // cdylib/staticlib/dylib
+}
+
+impl ArtifactKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ArtifactKind::Executable => "executable",
+            ArtifactKind::Library => "library",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub target: String,
+    pub package: String,
+    pub name: String,
+    pub kind: ArtifactKind,
+    pub path: PathBuf,
+    pub size_before_upx: Option<u64>,
+    // Of the shipped file, compressed or not
+    pub hardening: Option<Hardening>,
+    pub hardening_before_upx: Option<Hardening>,
+}
+
+impl Artifact {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.name.clone())
+    }
+}
+
+// Collects the final outputs of the selected members from
+// `cargo build --message-format=json-render-diagnostics` stdout
+pub fn parse_cargo_messages(
+    stdout: &str,
+    members: &[&WorkspaceMember],
+    target: &str,
+) -> Result<Vec<Artifact>, Box<dyn Error>> {
+    let mut artifacts = Vec::new();
+
+    for line in stdout.lines().filter(|line| line.starts_with('{')) {
+        let message: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| format!("Failed to parse cargo message: {e}"))?;
+        if message["reason"] != "compiler-artifact" {
+            continue;
+        }
+
+        let manifest_path = message["manifest_path"].as_str().unwrap_or_default();
+        let Some(member) = members
+            .iter()
+            .find(|m| m.manifest_path == Path::new(manifest_path))
+        else {
+            continue;
+        };
+        let target_name = message["target"]["name"].as_str().unwrap_or_default();
+
+        if let Some(executable) = message["executable"].as_str() {
+            artifacts.push(Artifact {
+                target: target.to_string(),
+                package: member.name.clone(),
+                name: target_name.to_string(),
+                kind: ArtifactKind::Executable,
+                path: PathBuf::from(executable),
+                size_before_upx: None,
+                hardening: None,
+                hardening_before_upx: None,
+            });
+            continue;
+        }
+
+        let is_library = message["target"]["kind"]
+            .as_array()
+            .is_some_and(|kinds| kinds.iter().any(|k| LIBRARY_KINDS.iter().any(|l| k == l)));
+        if !is_library {
+            continue;
+        }
+        for filename in message["filenames"].as_array().into_iter().flatten() {
+            let Some(filename) = filename.as_str() else {
+                continue;
+            };
+            let path = PathBuf::from(filename);
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            if LIBRARY_EXTENSIONS.contains(&extension) {
+                artifacts.push(Artifact {
+                    target: target.to_string(),
+                    package: member.name.clone(),
+                    name: target_name.to_string(),
+                    kind: ArtifactKind::Library,
+                    path,
+                    size_before_upx: None,
+                    hardening: None,
+                    hardening_before_upx: None,
+                });
+            }
+        }
+    }
+
+    Ok(artifacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str) -> WorkspaceMember {
+        WorkspaceMember {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            description: None,
+            license: None,
+            authors: Vec::new(),
+            homepage: None,
+            repository: None,
+            manifest_path: PathBuf::from(format!("/ws/{name}/Cargo.toml")),
+            bin_targets: Vec::new(),
+            example_targets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn collects_member_executables_and_libraries() {
+        let stdout = r#"{"reason":"compiler-artifact","manifest_path":"/registry/serde/Cargo.toml","target":{"name":"serde","kind":["lib"]},"filenames":["/t/libserde.rlib"],"executable":null}
+{"reason":"compiler-artifact","manifest_path":"/ws/app/Cargo.toml","target":{"name":"app-cli","kind":["bin"]},"filenames":["/t/app-cli"],"executable":"/t/app-cli"}
+{"reason":"compiler-artifact","manifest_path":"/ws/app/Cargo.toml","target":{"name":"app","kind":["cdylib","rlib"]},"filenames":["/t/libapp.so","/t/libapp.rlib"],"executable":null}
+{"reason":"build-finished","success":true}
+"#;
+        let app = member("app");
+        let artifacts = parse_cargo_messages(stdout, &[&app], "x86_64-unknown-linux-gnu").unwrap();
+
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0].name, "app-cli");
+        assert_eq!(artifacts[0].kind, ArtifactKind::Executable);
+        assert_eq!(artifacts[0].path, PathBuf::from("/t/app-cli"));
+        assert_eq!(artifacts[1].kind, ArtifactKind::Library);
+        assert_eq!(artifacts[1].path, PathBuf::from("/t/libapp.so"));
+    }
+
+    #[test]
+    fn ignores_build_scripts_and_unselected_members() {
+        let stdout = r#"{"reason":"compiler-artifact","manifest_path":"/ws/app/Cargo.toml","target":{"name":"build-script-build","kind":["custom-build"]},"filenames":["/t/build-script-build"],"executable":null}
+{"reason":"compiler-artifact","manifest_path":"/ws/other/Cargo.toml","target":{"name":"other","kind":["bin"]},"filenames":["/t/other"],"executable":"/t/other"}
+"#;
+        let app = member("app");
+        assert!(
+            parse_cargo_messages(stdout, &[&app], "x86_64-unknown-linux-gnu")
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::build_system::BuildMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanicStrategy {
+    Unwind,
+    Abort,
+    ImmediateAbort, // -Cpanic=immediate-abort, nightly only
+}
+
+impl PanicStrategy {
+    pub fn default_for(mode: BuildMode) -> Self {
+        match mode {
+            BuildMode::Nightly => PanicStrategy::ImmediateAbort,
+            BuildMode::Stable => PanicStrategy::Abort,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PanicStrategy::Unwind => "unwind",
+            PanicStrategy::Abort => "abort",
+            PanicStrategy::ImmediateAbort => "immediate-abort",
+        }
+    }
+
+    // Value of `panic` in the cargo profile
+    pub fn profile_value(self) -> &'static str {
+        match self {
+            PanicStrategy::Unwind => "unwind",
+            PanicStrategy::Abort | PanicStrategy::ImmediateAbort => "abort",
+        }
+    }
+}
+
+impl std::str::FromStr for PanicStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unwind" => Ok(PanicStrategy::Unwind),
+            "abort" => Ok(PanicStrategy::Abort),
+            "immediate-abort" => Ok(PanicStrategy::ImmediateAbort),
+            _ => Err(format!(
+                "Unknown panic strategy `{s}` (expected unwind, abort or immediate-abort)"
+            )),
+        }
+    }
+}
+
+// `-Z build-std` crate set, its features and the panic strategy
+pub struct BuildStdOptions {
+    pub crates: Option<Vec<String>>,
+    pub features: Vec<String>,
+    pub panic: PanicStrategy,
+}
+
+impl BuildStdOptions {
+    // Explicit crate list, or std plus the runtime matching the panic strategy
+    pub fn crates(&self) -> Vec<String> {
+        if let Some(crates) = &self.crates {
+            return crates.clone();
+        }
+        let runtime = match self.panic {
+            PanicStrategy::Unwind => "panic_unwind",
+            PanicStrategy::Abort | PanicStrategy::ImmediateAbort => "panic_abort",
+        };
+        vec!["std".to_string(), runtime.to_string()]
+    }
+
+    // `profile_panic` is the `panic` value cargo will actually use for the release profile
+    pub fn validate(
+        &self,
+        mode: BuildMode,
+        profile_panic: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        if mode == BuildMode::Stable {
+            if self.panic == PanicStrategy::ImmediateAbort {
+                return Err("Panic strategy `immediate-abort` requires --mode nightly".into());
+            }
+            if self.crates.is_some() || !self.features.is_empty() {
+                return Err("build-std crates and features require --mode nightly".into());
+            }
+        }
+
+        if let Some(profile_panic) = profile_panic
+            && profile_panic != self.panic.profile_value()
+        {
+            return Err(format!(
+                "Panic strategy `{}` conflicts with `panic = \"{profile_panic}\"` in the release profile",
+                self.panic.name()
+            )
+            .into());
+        }
+
+        let crates = self.crates();
+        if mode == BuildMode::Nightly
+            && self.panic != PanicStrategy::Unwind
+            && crates.iter().any(|c| c == "std")
+            && !crates.iter().any(|c| c == "panic_abort")
+        {
+            return Err(format!(
+                "Panic strategy `{}` needs `panic_abort` in the build-std crate list",
+                self.panic.name()
+            )
+            .into());
+        }
+
+        if self.panic == PanicStrategy::Unwind
+            && self.features.iter().any(|f| f == "panic_immediate_abort")
+        {
+            return Err(
+                "build-std feature `panic_immediate_abort` cannot be combined with panic strategy `unwind`"
+                    .into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "-Z".to_string(),
+            format!("build-std={}", self.crates().join(",")),
+        ];
+        if !self.features.is_empty() {
+            args.push("-Z".to_string());
+            args.push(format!("build-std-features={}", self.features.join(",")));
+        }
+        args
+    }
+
+    pub fn rustflags(&self) -> Vec<&'static str> {
+        match self.panic {
+            PanicStrategy::ImmediateAbort => vec!["-Zunstable-options", "-Cpanic=immediate-abort"],
+            PanicStrategy::Unwind | PanicStrategy::Abort => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(panic: PanicStrategy, crates: &[&str], features: &[&str]) -> BuildStdOptions {
+        BuildStdOptions {
+            crates: Some(crates.iter().map(|c| c.to_string()).collect()),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            panic,
+        }
+    }
+
+    #[test]
+    fn default_nightly_setup_is_consistent() {
+        let opts = BuildStdOptions {
+            crates: None,
+            features: Vec::new(),
+            panic: PanicStrategy::default_for(BuildMode::Nightly),
+        };
+        assert!(opts.validate(BuildMode::Nightly, Some("abort")).is_ok());
+        assert_eq!(opts.cargo_args(), ["-Z", "build-std=std,panic_abort"]);
+    }
+
+    #[test]
+    fn manifest_panic_must_match_strategy() {
+        let opts = options(PanicStrategy::Abort, &["std", "panic_abort"], &[]);
+        assert!(opts.validate(BuildMode::Nightly, Some("unwind")).is_err());
+        let opts = options(PanicStrategy::Unwind, &["std", "panic_unwind"], &[]);
+        assert!(opts.validate(BuildMode::Nightly, Some("unwind")).is_ok());
+    }
+
+    #[test]
+    fn abort_needs_panic_abort_runtime_with_std() {
+        let opts = options(PanicStrategy::ImmediateAbort, &["std"], &[]);
+        assert!(opts.validate(BuildMode::Nightly, None).is_err());
+        let opts = options(PanicStrategy::Abort, &["core", "alloc"], &[]);
+        assert!(opts.validate(BuildMode::Nightly, None).is_ok());
+    }
+
+    #[test]
+    fn immediate_abort_feature_conflicts_with_unwind() {
+        let opts = options(
+            PanicStrategy::Unwind,
+            &["std", "panic_unwind"],
+            &["panic_immediate_abort"],
+        );
+        assert!(opts.validate(BuildMode::Nightly, None).is_err());
+    }
+
+    #[test]
+    fn stable_rejects_nightly_only_settings() {
+        let opts = options(PanicStrategy::ImmediateAbort, &["std", "panic_abort"], &[]);
+        assert!(opts.validate(BuildMode::Stable, None).is_err());
+        let opts = options(PanicStrategy::Abort, &["std", "panic_abort"], &[]);
+        assert!(opts.validate(BuildMode::Stable, None).is_err());
+        let opts = BuildStdOptions {
+            crates: None,
+            features: Vec::new(),
+            panic: PanicStrategy::Abort,
+        };
+        assert!(opts.validate(BuildMode::Stable, Some("abort")).is_ok());
+    }
+}
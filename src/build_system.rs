@@ -1,134 +1,563 @@
-use std::env::consts::EXE_SUFFIX;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
-use crate::cargo_config::CargoConfigManager;
+use serde::{Deserialize, Serialize};
+
+use crate::analyze;
+use crate::artifact::{self, Artifact, ArtifactKind};
+use crate::build_std::BuildStdOptions;
+use crate::cargo_config::{self, CargoConfigManager, ProfileMode, ProfileSettings};
+use crate::checksum::{self, Algorithm};
 use crate::dependency_checker;
+use crate::dry_run;
+use crate::features::FeatureSelection;
+use crate::hardening::{self, HardeningConfig};
+use crate::package::{self, PackageInput, PackageOptions};
+use crate::report::{self, ArtifactReport, BuildReport, StepReport, StepStatus, TargetReport};
+use crate::rustflags::{self, UserRustflags};
+use crate::size_history::{self, HistoryEntry, SizeHistory, SizeLimits};
+use crate::static_linking::{self, StaticLinking};
+use crate::workspace::{self, MemberCheck, Workspace, WorkspaceMember};
 
-pub struct BuildSystem {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildMode {
+    #[default]
+    Nightly, // nightly + build-std + immediate-abort
+    Stable, // 仅使用 release profile 优化
+}
+
+impl BuildMode {
+    pub fn requires_nightly(self) -> bool {
+        self == BuildMode::Nightly
+    }
+
+    pub fn toolchain(self) -> &'static str {
+        match self {
+            BuildMode::Nightly => "+nightly",
+            BuildMode::Stable => "+stable",
+        }
+    }
+}
+
+impl std::str::FromStr for BuildMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nightly" => Ok(BuildMode::Nightly),
+            "stable" => Ok(BuildMode::Stable),
+            _ => Err(format!(
+                "Unknown build mode `{s}` (expected nightly or stable)"
+            )),
+        }
+    }
+}
+
+// Effective build settings after merging CLI flags with the project configuration
+pub struct BuildOptions {
+    pub mode: BuildMode,
+    pub build_std: BuildStdOptions,
+    pub targets: Vec<String>,
+    pub keep_going: bool,
+    pub use_upx: bool,
+    pub upx_args: Vec<String>,
+    pub clean: bool,
+    pub profile: String,
+    pub profile_mode: ProfileMode,
+    pub profile_settings: ProfileSettings,
+    pub rustflags: Vec<String>,
+    pub packages: Vec<String>,
+    pub bins: Vec<String>,
+    pub all_bins: bool,
+    pub examples: Vec<String>,
+    pub features: FeatureSelection,
+    pub dry_run: bool,
+    pub analyze: bool,
+    pub report: bool,
+    pub report_file: Option<PathBuf>,
+    pub history_file: PathBuf,
+    pub baseline: Option<String>,
+    pub size_limits: SizeLimits,
+    pub checksums: Vec<Algorithm>,
+    // Targets are already the static ones, see `static_linking::static_target`
+    pub static_linking: Option<StaticLinking>,
+    pub hardening: HardeningConfig,
+}
+
+// Outcome of building the selected packages for one target triple
+struct TargetBuild {
     target: String,
-    use_upx: bool,
-    clean: bool,
-    executable: PathBuf,
+    result: Result<Vec<Artifact>, String>,
+}
+
+pub struct BuildSystem {
+    options: BuildOptions,
+    workspace: Workspace,
+    // Recorded for the JSON report
+    steps: RefCell<Vec<StepReport>>,
+    builds: RefCell<Vec<TargetBuild>>,
 }
 
 impl BuildSystem {
-    pub fn new(target: &str, use_upx: bool, clean: bool) -> Result<Self, Box<dyn Error>> {
-        // Check dependencies
-        dependency_checker::check_command("cargo")?;
-        dependency_checker::check_rust_nightly()?;
-        if use_upx {
-            dependency_checker::check_upx_lzma()?;
+    pub fn new(options: BuildOptions, workspace: Workspace) -> Result<Self, Box<dyn Error>> {
+        // Check dependencies; a dry run only prints the commands
+        if !options.dry_run {
+            dependency_checker::check_command("cargo")?;
+            if options.mode.requires_nightly() {
+                dependency_checker::check_rust_nightly()?;
+            }
+            if options.use_upx {
+                if options.upx_args.iter().any(|arg| arg == "--lzma") {
+                    dependency_checker::check_upx_lzma()?;
+                } else {
+                    dependency_checker::check_command("upx")?;
+                }
+            }
         }
 
-        // Get project name
-        let project_name = Self::parse_project_name()?;
-
-        // Get target directory
-        let target_dir = Self::get_target_directory()?;
+        // The panic strategy has to agree with the profile cargo will use
+        cargo_config::validate_profile_name(&options.profile)?;
+        let profile_panic = cargo_config::effective_profile_setting(
+            &workspace.root_manifest(),
+            &options.profile,
+            &options.profile_settings,
+            "panic",
+        )?;
+        options
+            .build_std
+            .validate(options.mode, profile_panic.as_deref())?;
+        options.hardening.validate()?;
 
-        // Get executable path
-        let executable = Self::get_executable_path(&target_dir, &project_name, target)?;
+        // Fail early on unknown packages or targets
+        workspace.select_binaries(&options.packages, &options.bins, &options.examples)?;
 
         Ok(Self {
-            target: target.to_string(),
-            use_upx,
-            clean,
-            executable,
+            options,
+            workspace,
+            steps: RefCell::new(Vec::new()),
+            builds: RefCell::new(Vec::new()),
         })
     }
 
-    fn parse_project_name() -> Result<String, Box<dyn Error>> {
-        let cargo_toml = std::fs::read_to_string("Cargo.toml")?;
-        let name_line = cargo_toml
-            .lines()
-            .find(|line| line.trim().starts_with("name = "))
-            .ok_or("Failed to find project name in Cargo.toml")?;
+    // Packages passed to `cargo build`
+    fn built_members(&self) -> Result<Vec<&WorkspaceMember>, Box<dyn Error>> {
+        self.workspace.select_binaries(
+            &self.options.packages,
+            &self.options.bins,
+            &self.options.examples,
+        )
+    }
+
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        if self.options.dry_run {
+            return self.plan();
+        }
+
+        // Modify and restore Cargo config only when explicitly requested
+        let mut config = match self.options.profile_mode {
+            ProfileMode::Manifest => {
+                let mut config = CargoConfigManager::new(&self.workspace.root_manifest())?;
+                config.ensure_profile(&self.options.profile, &self.options.profile_settings)?;
+                Some(config)
+            }
+            ProfileMode::Environment => None,
+        };
+
+        // Read before `cargo clean` can remove it from the target directory
+        let mut history = SizeHistory::load(&self.options.history_file)?;
+        let commit = size_history::git_commit(&self.workspace.root);
+        let commit_id = commit.as_ref().map(|(id, _)| id.as_str());
+
+        if self.options.static_linking.is_some() {
+            self.step("setup", None, || {
+                for mut cmd in self.setup_commands()? {
+                    dry_run::print_command(&cmd);
+                    if !cmd.status()?.success() {
+                        return Err(
+                            "Failed to install the Rust components for the static build".into()
+                        );
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        if self.options.clean {
+            self.step("clean", None, || self.clean())?;
+        }
+
+        for target in &self.options.targets {
+            self.record_build(target, self.build_target(target))?;
+        }
+
+        let builds = self.builds.borrow();
+        self.show_result(&builds, &history, commit_id)?;
+
+        // Gates compare against the history as it was before this build
+        let mut violations = Vec::new();
+        let mut hardening_violations = Vec::new();
+        let mut entries = Vec::new();
+        for build in builds.iter() {
+            let Ok(artifacts) = &build.result else {
+                continue;
+            };
+            for artifact in artifacts {
+                hardening_violations.extend(self.options.hardening.violations(artifact));
+            }
+            let sizes = artifact_sizes(artifacts)?;
+            let baseline =
+                history.baseline(&build.target, commit_id, self.options.baseline.as_deref());
+            violations.extend(
+                self.options
+                    .size_limits
+                    .violations(&build.target, &sizes, baseline),
+            );
+            entries.push(HistoryEntry {
+                commit: commit.as_ref().map(|(id, _)| id.clone()),
+                dirty: commit.as_ref().is_some_and(|(_, dirty)| *dirty),
+                timestamp: size_history::now(),
+                target: build.target.clone(),
+                sizes,
+            });
+        }
+        for entry in entries {
+            history.record(entry);
+        }
+        history.save()?;
 
-        let name = name_line
-            .split('=')
-            .nth(1)
-            .ok_or("Invalid name format in Cargo.toml")?
-            .trim()
-            .trim_matches('"')
-            .to_string();
+        if let Some(config) = config.as_mut() {
+            config.restore()?;
+        }
 
-        Ok(name)
+        let failed = failed_targets(&builds);
+        if !failed.is_empty() {
+            return Err(format!("Build failed for targets: {}", failed.join(", ")).into());
+        }
+        if !violations.is_empty() {
+            return Err(format!("Size limits exceeded: {}", violations.join("; ")).into());
+        }
+        if !hardening_violations.is_empty() {
+            return Err(format!(
+                "Hardening requirements not met: {}",
+                hardening_violations.join("; ")
+            )
+            .into());
+        }
+        Ok(())
     }
 
-    fn get_target_directory() -> Result<PathBuf, Box<dyn Error>> {
-        let output = Command::new("cargo")
-            .args(["metadata", "--format-version=1", "--no-deps"])
-            .output()?;
+    // `package`: one archive per member, target and format from the artifacts
+    // of the targets that built
+    pub fn run_package(&self, options: &PackageOptions) -> Result<(), Box<dyn Error>> {
+        self.step("package", None, || {
+            let members = self.built_members()?;
+            if self.options.dry_run {
+                println!();
+                for target in &self.options.targets {
+                    for member in &members {
+                        for &format in &options.formats {
+                            let name = package::file_name(format, member, target, options)?;
+                            println!("Would write {}", options.dist_dir.join(name).display());
+                        }
+                    }
+                }
+                return Ok(());
+            }
 
-        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
-        let target_dir = metadata["target_directory"]
-            .as_str()
-            .ok_or("Failed to get target directory")?;
+            let mtime = package::source_date_epoch();
+            let mut written = Vec::new();
+            for build in self.builds.borrow().iter() {
+                let Ok(artifacts) = &build.result else {
+                    continue;
+                };
+                for member in &members {
+                    let owned: Vec<&Artifact> = artifacts
+                        .iter()
+                        .filter(|a| a.package == member.name)
+                        .collect();
+                    if owned.is_empty() {
+                        continue;
+                    }
+                    let input = PackageInput {
+                        member,
+                        target: &build.target,
+                        workspace_root: &self.workspace.root,
+                        files: package::collect_files(
+                            member,
+                            &self.workspace.root,
+                            &owned,
+                            options.include.as_deref(),
+                        )?,
+                        mtime,
+                    };
+                    for &format in &options.formats {
+                        let path = package::write_package(format, &input, options)?;
+                        println!("Packaged {}", path.display());
+                        written.push(path);
+                    }
+                }
+            }
+            if written.is_empty() {
+                return Err("No artifacts to package".into());
+            }
+            if !self.options.checksums.is_empty() {
+                for manifest in checksum::write_manifests(&written, &self.options.checksums)? {
+                    println!("Checksums written to {}", manifest.display());
+                }
+            }
+            Ok(())
+        })
+    }
 
-        Ok(PathBuf::from(target_dir))
+    // `--dry-run`: print what `run` would do without running or writing anything
+    fn plan(&self) -> Result<(), Box<dyn Error>> {
+        let profile = &self.options.profile;
+        match self.options.profile_mode {
+            ProfileMode::Manifest => match cargo_config::profile_manifest_diff(
+                &self.workspace.root_manifest(),
+                profile,
+                &self.options.profile_settings,
+            )? {
+                Some(diff) => {
+                    println!("\nCargo.toml changes (restored after the build):");
+                    print!("{diff}");
+                }
+                None => println!("\nCargo.toml already sets every `profile.{profile}` key"),
+            },
+            ProfileMode::Environment => println!(
+                "\nCargo.toml is not modified; profile `{profile}` is passed through the environment"
+            ),
+        }
+
+        if self.options.static_linking.is_some() {
+            for cmd in self.setup_commands()? {
+                println!();
+                dry_run::print_command(&cmd);
+            }
+        }
+        if self.options.clean {
+            println!();
+            dry_run::print_command(&Self::clean_command());
+        }
+        let members = self.built_members()?;
+        for target in &self.options.targets {
+            println!();
+            let user_flags = UserRustflags::resolve(target, self.options.mode.toolchain())?;
+            dry_run::print_command(&self.build_command(target, &members, user_flags)?);
+            if self.options.use_upx {
+                for path in self.expected_executables(target, &members) {
+                    dry_run::print_command(&self.upx_command(&path));
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn get_executable_path(
-        target_dir: &Path,
-        project_name: &str,
-        target: &str,
-    ) -> Result<PathBuf, Box<dyn Error>> {
-        let platform_suffix = if target.contains("uefi") {
-            ".efi"
+    // Where cargo will place the executables of a build, used by `--dry-run`
+    // since the real paths are only known from cargo's messages
+    fn expected_executables(&self, target: &str, members: &[&WorkspaceMember]) -> Vec<PathBuf> {
+        let dir = self
+            .workspace
+            .target_directory
+            .join(target)
+            .join(&self.options.profile);
+        let suffix = if target.contains("windows") {
+            ".exe"
         } else {
-            EXE_SUFFIX
+            ""
         };
+        let explicit = !self.options.bins.is_empty() || !self.options.examples.is_empty();
 
-        Ok(target_dir
-            .join(target)
-            .join("release")
-            .join(format!("{project_name}{platform_suffix}")))
+        let mut paths = Vec::new();
+        for member in members {
+            for bin in &member.bin_targets {
+                if !explicit || self.options.all_bins || self.options.bins.contains(bin) {
+                    paths.push(dir.join(format!("{bin}{suffix}")));
+                }
+            }
+            for example in &member.example_targets {
+                if self.options.examples.contains(example) {
+                    paths.push(dir.join("examples").join(format!("{example}{suffix}")));
+                }
+            }
+        }
+        paths
     }
 
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        // Modify and restore Cargo config
-        let mut config = CargoConfigManager::new("Cargo.toml")?;
-        config.ensure_release_profile()?;
+    fn build_target(&self, target: &str) -> Result<Vec<Artifact>, Box<dyn Error>> {
+        let mut artifacts = self.step("build", Some(target), || self.build(target))?;
+
+        if self.options.static_linking.is_some() {
+            self.step("verify-static", Some(target), || {
+                Self::verify_static(&artifacts)
+            })?;
+        }
 
-        if self.clean {
-            self.clean()?;
+        // Before UPX, which leaves nothing to parse
+        self.step("inspect", Some(target), || Self::inspect(&mut artifacts))?;
+        if self.options.analyze {
+            self.step("analyze", Some(target), || {
+                Self::analyze(&artifacts);
+                Ok(())
+            })?;
         }
 
-        self.build()?;
+        if self.options.use_upx {
+            self.step("upx", Some(target), || self.compress(&mut artifacts))?;
+        }
+        if !self.options.checksums.is_empty() {
+            self.step("checksums", Some(target), || {
+                self.write_checksums(&artifacts)
+            })?;
+        }
+        Ok(artifacts)
+    }
 
-        if self.use_upx {
-            self.compress()?;
+    // Keeps the outcome for the summary and the report; a failure stops the
+    // run unless `--keep-going` is set
+    fn record_build(
+        &self,
+        target: &str,
+        result: Result<Vec<Artifact>, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.builds.borrow_mut().push(TargetBuild {
+            target: target.to_string(),
+            result: result.as_ref().map_err(|e| e.to_string()).cloned(),
+        });
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if self.options.keep_going => {
+                eprintln!("Build for {target} failed: {e}");
+                Ok(())
+            }
+            Err(e) => Err(e),
         }
+    }
 
-        self.show_result()?;
+    // Runs one step and records its outcome and duration for the report
+    fn step<T>(
+        &self,
+        name: &str,
+        target: Option<&str>,
+        f: impl FnOnce() -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let started = Instant::now();
+        let result = f();
+        self.steps.borrow_mut().push(StepReport::new(
+            name,
+            target,
+            started.elapsed(),
+            result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+        ));
+        result
+    }
 
-        config.restore()?;
+    // The musl targets ship their C runtime in rust-std, which build-std does
+    // not replace
+    fn setup_commands(&self) -> Result<Vec<Command>, Box<dyn Error>> {
+        dependency_checker::rustup_setup_commands(
+            self.options.mode.toolchain(),
+            &self.options.targets,
+            self.options.mode == BuildMode::Nightly,
+        )
+    }
+
+    fn verify_static(artifacts: &[Artifact]) -> Result<(), Box<dyn Error>> {
+        for artifact in artifacts {
+            if artifact.kind == ArtifactKind::Executable {
+                static_linking::verify(&artifact.path)?;
+            }
+        }
         Ok(())
     }
 
     fn clean(&self) -> Result<(), Box<dyn Error>> {
         println!("Cleaning previous build files...");
-        Command::new("cargo").args(["clean"]).status()?;
+        Self::clean_command().status()?;
         Ok(())
     }
 
-    fn build(&self) -> Result<(), Box<dyn Error>> {
+    fn clean_command() -> Command {
+        let mut cmd = Command::new("cargo");
+        cmd.arg("clean");
+        cmd
+    }
+
+    // `user_flags` are resolved by the caller, so tests don't depend on the
+    // environment or on cargo config files around them
+    fn build_command(
+        &self,
+        target: &str,
+        members: &[&WorkspaceMember],
+        user_flags: UserRustflags,
+    ) -> Result<Command, Box<dyn Error>> {
+        println!("Target: {target}");
+        println!("Profile: {}", self.options.profile);
+        // User flags first, then ours, then the project's and the command line's
+        let mut rustflags = user_flags.flags;
+        rustflags.extend(
+            self.options
+                .build_std
+                .rustflags()
+                .into_iter()
+                .map(str::to_string),
+        );
+        if self.options.static_linking.is_some() {
+            rustflags.extend(static_linking::CRT_STATIC_FLAGS.map(str::to_string));
+        }
+        rustflags.extend(self.options.rustflags.iter().cloned());
+        println!(
+            "Rustflags (user flags from {}): {}",
+            user_flags.source,
+            rustflags.join(" ")
+        );
+        let mut cmd = Command::new("cargo");
+        if self.options.profile_mode == ProfileMode::Environment {
+            cmd.envs(cargo_config::profile_env(
+                &self.workspace.root_manifest(),
+                &self.options.profile,
+                &self.options.profile_settings,
+            )?);
+        }
+        cmd.args([self.options.mode.toolchain(), "build"]);
+        if self.options.mode == BuildMode::Nightly {
+            cmd.args(self.options.build_std.cargo_args());
+        }
+        cmd.args([
+            "--target",
+            target,
+            "--profile",
+            &self.options.profile,
+            "--message-format=json-render-diagnostics",
+        ]);
+        for member in members {
+            cmd.args(["-p", &member.name]);
+        }
+        cmd.args(self.options.features.cargo_args());
+        for bin in &self.options.bins {
+            cmd.args(["--bin", bin]);
+        }
+        if self.options.all_bins {
+            cmd.arg("--bins");
+        }
+        for example in &self.options.examples {
+            cmd.args(["--example", example]);
+        }
+        cmd.env_remove("RUSTFLAGS")
+            .env("CARGO_ENCODED_RUSTFLAGS", rustflags::encode(&rustflags));
+        Ok(cmd)
+    }
+
+    fn build(&self, target: &str) -> Result<Vec<Artifact>, Box<dyn Error>> {
         println!("Building optimized executable...");
-        println!("Target: {}", self.target);
-        let rustflags = "-Zunstable-options -Cpanic=immediate-abort";
-        let output = Command::new("cargo")
-            .args([
-                "+nightly",
-                "build",
-                "-Z",
-                "build-std=std,panic_abort",
-                "--target",
-                &self.target,
-                "--release",
-            ])
-            .env("RUSTFLAGS", rustflags)
+        let members = self.built_members()?;
+        let user_flags = UserRustflags::resolve(target, self.options.mode.toolchain())?;
+        let output = self
+            .build_command(target, &members, user_flags)?
             .output()
             .map_err(|e| format!("Failed to execute cargo command: {e}"))?;
 
@@ -141,50 +570,558 @@ impl BuildSystem {
             .into());
         }
 
-        println!("Build complete!");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let artifacts = artifact::parse_cargo_messages(&stdout, &members, target)?;
+        if artifacts.is_empty() {
+            return Err("Cargo build finished without producing any artifacts".into());
+        }
+
+        Ok(artifacts)
+    }
+
+    fn compress(&self, artifacts: &mut [Artifact]) -> Result<(), Box<dyn Error>> {
+        for artifact in artifacts {
+            if artifact.kind != ArtifactKind::Executable {
+                continue;
+            }
+            println!("Compressing with UPX: {}", artifact.path.display());
+            let size_before = artifact.path.metadata()?.len();
+            let status = self.upx_command(&artifact.path).status()?;
+            if !status.success() {
+                return Err(format!("UPX compression failed for {}", artifact.file_name()).into());
+            }
+            artifact.size_before_upx = Some(size_before);
+            // The gates and the report describe the file that ships
+            artifact.hardening_before_upx = artifact.hardening.take();
+            artifact.hardening = hardening::inspect(&artifact.path)?;
+        }
         Ok(())
     }
 
-    fn compress(&self) -> Result<(), Box<dyn Error>> {
-        println!("Compressing with UPX: {}", self.executable.display());
-        Command::new("upx")
-            .args([
-                "--best",
-                "--lzma",
-                self.executable
-                    .to_str()
-                    .ok_or("Failed to convert executable path to string")?,
-            ])
-            .status()?;
+    fn inspect(artifacts: &mut [Artifact]) -> Result<(), Box<dyn Error>> {
+        for artifact in artifacts {
+            artifact.hardening = hardening::inspect(&artifact.path)?;
+        }
         Ok(())
     }
 
-    fn show_result(&self) -> Result<(), Box<dyn Error>> {
-        let size_kb = self.executable.metadata()?.len() as f64 / 1024.0;
-        println!("\nBuild complete! Final size: {size_kb:.1} KB");
-        println!("Executable path: {}", self.executable.display());
+    fn analyze(artifacts: &[Artifact]) {
+        for artifact in artifacts {
+            println!(
+                "\nSize breakdown of {} ({}):",
+                artifact.file_name(),
+                artifact.target
+            );
+            match analyze::analyze(&artifact.path) {
+                Ok(breakdown) => breakdown.print(analyze::DEFAULT_TOP),
+                Err(e) => println!("{e}"),
+            }
+        }
+    }
+
+    // Digests of the final, post-UPX files
+    fn write_checksums(&self, artifacts: &[Artifact]) -> Result<(), Box<dyn Error>> {
+        let files: Vec<PathBuf> = artifacts.iter().map(|a| a.path.clone()).collect();
+        for manifest in checksum::write_manifests(&files, &self.options.checksums)? {
+            println!("Checksums written to {}", manifest.display());
+        }
+        Ok(())
+    }
+
+    fn upx_command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new("upx");
+        cmd.args(&self.options.upx_args).arg(path);
+        cmd
+    }
+
+    fn show_result(
+        &self,
+        builds: &[TargetBuild],
+        history: &SizeHistory,
+        commit: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("\nBuild complete!");
+        let mut total = 0;
+        let mut count = 0;
+        for build in builds {
+            let Ok(artifacts) = &build.result else {
+                continue;
+            };
+            if builds.len() > 1 {
+                println!("\n[{}]", build.target);
+            }
+            let baseline =
+                history.baseline(&build.target, commit, self.options.baseline.as_deref());
+            if let (None, Some(requested)) = (baseline, &self.options.baseline) {
+                println!(
+                    "No size history for baseline {requested} on {}",
+                    build.target
+                );
+            }
+            for artifact in artifacts {
+                let size = artifact.path.metadata()?.len();
+                total += size;
+                count += 1;
+                let size_kb = size as f64 / 1024.0;
+                match artifact.size_before_upx {
+                    Some(before) => println!(
+                        "{} ({}): {size_kb:.1} KB (UPX: {:.1} KB -> {size_kb:.1} KB, {:.1}%)",
+                        artifact.file_name(),
+                        artifact.package,
+                        before as f64 / 1024.0,
+                        size as f64 * 100.0 / before as f64
+                    ),
+                    None => println!(
+                        "{} ({}): {size_kb:.1} KB",
+                        artifact.file_name(),
+                        artifact.package
+                    ),
+                }
+                if let Some(baseline) = baseline {
+                    println!(
+                        "  Delta: {}",
+                        size_delta(baseline, &artifact.file_name(), size)
+                    );
+                }
+                if let Some(hardening) = &artifact.hardening {
+                    println!("  Hardening: {}", hardening.summary());
+                }
+                if let Some(hardening) = &artifact.hardening_before_upx {
+                    println!("  Hardening before UPX: {}", hardening.summary());
+                }
+                println!("  Path: {}", artifact.path.display());
+            }
+        }
+        if count > 1 {
+            println!("Total: {count} artifacts, {:.1} KB", total as f64 / 1024.0);
+        }
+        if builds.len() > 1 {
+            Self::show_matrix(builds)?;
+        }
         Ok(())
     }
 
+    fn show_matrix(builds: &[TargetBuild]) -> Result<(), Box<dyn Error>> {
+        println!();
+        for line in matrix_lines(builds)? {
+            println!("{line}");
+        }
+        Ok(())
+    }
+
+    // Checks cover every member unless `-p` narrowed the selection
+    fn checked_members(&self) -> Result<Vec<&WorkspaceMember>, Box<dyn Error>> {
+        self.workspace.select(&self.options.packages)
+    }
+
     pub fn run_clippy(&self) -> Result<(), Box<dyn Error>> {
+        self.step("clippy", None, || self.clippy())
+    }
+
+    fn clippy(&self) -> Result<(), Box<dyn Error>> {
         println!("\nRunning clippy lint checks...");
-        let status = Command::new("cargo")
-            .args(["clippy", "--", "-D", "warnings"])
-            .status()?;
-        if !status.success() {
-            return Err("Clippy checks failed: non-zero exit code".into());
+        let mut results = Vec::new();
+        for member in self.checked_members()? {
+            let mut cmd = Command::new("cargo");
+            cmd.args(["clippy", "-p", &member.name])
+                .args(self.options.features.cargo_args())
+                .args(["--", "-D", "warnings"]);
+            if self.options.dry_run {
+                dry_run::print_command(&cmd);
+                continue;
+            }
+            let status = cmd.status()?;
+            results.push(MemberCheck {
+                name: member.name.clone(),
+                passed: status.success(),
+            });
         }
-        println!("Clippy checks passed");
-        Ok(())
+        if self.options.dry_run {
+            return Ok(());
+        }
+        workspace::summarize_checks("Clippy", &results)
     }
 
     pub fn run_cargo_deny(&self) -> Result<(), Box<dyn Error>> {
+        self.step("deny", None, || self.cargo_deny())
+    }
+
+    fn cargo_deny(&self) -> Result<(), Box<dyn Error>> {
         println!("\nRunning cargo-deny checks...");
-        let status = Command::new("cargo").args(["deny", "check"]).status()?;
-        if !status.success() {
-            return Err("Cargo-deny checks failed: non-zero exit code".into());
+        let mut results = Vec::new();
+        for member in self.checked_members()? {
+            let mut cmd = Command::new("cargo");
+            cmd.arg("deny")
+                .arg("--manifest-path")
+                .arg(&member.manifest_path)
+                .arg("check");
+            if self.options.dry_run {
+                dry_run::print_command(&cmd);
+                continue;
+            }
+            let status = cmd.status()?;
+            results.push(MemberCheck {
+                name: member.name.clone(),
+                passed: status.success(),
+            });
+        }
+        if self.options.dry_run {
+            return Ok(());
+        }
+        workspace::summarize_checks("Cargo-deny", &results)
+    }
+
+    pub fn run_depcheck(&self) -> Result<(), Box<dyn Error>> {
+        self.step("depcheck", None, || self.depcheck())
+    }
+
+    fn depcheck(&self) -> Result<(), Box<dyn Error>> {
+        if self.options.dry_run {
+            println!("\nScanning for unused dependencies...");
+            for member in self.checked_members()? {
+                dry_run::print_command(&dependency_checker::udeps_command(
+                    &member.name,
+                    &self.options.features,
+                ));
+            }
+            println!("(`cargo remove` only runs for dependencies confirmed interactively)");
+            return Ok(());
         }
-        println!("Cargo-deny checks passed");
+        dependency_checker::check_unused_dependencies(
+            &self.checked_members()?,
+            &self.options.features,
+        )?;
         Ok(())
     }
+
+    // Writes the `--report json` document, also after a failed step
+    pub fn write_report(&self, success: bool) -> Result<(), Box<dyn Error>> {
+        if !self.options.report || self.options.dry_run {
+            return Ok(());
+        }
+
+        let mut targets = Vec::new();
+        for build in self.builds.borrow().iter() {
+            targets.push(match &build.result {
+                Ok(artifacts) => TargetReport {
+                    target: build.target.clone(),
+                    status: StepStatus::Passed,
+                    error: None,
+                    artifacts: artifacts
+                        .iter()
+                        .map(ArtifactReport::from_artifact)
+                        .collect::<Result<_, _>>()?,
+                },
+                Err(e) => TargetReport {
+                    target: build.target.clone(),
+                    status: StepStatus::Failed,
+                    error: Some(e.clone()),
+                    artifacts: Vec::new(),
+                },
+            });
+        }
+
+        let report = BuildReport {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            toolchain: report::Toolchain::detect(self.options.mode.toolchain()),
+            mode: self.options.mode,
+            profile: self.options.profile.clone(),
+            profile_settings: report::profile_values(&self.options.profile_settings),
+            success,
+            steps: self.steps.borrow().clone(),
+            targets,
+        };
+        report.write(self.options.report_file.as_deref())
+    }
+}
+
+fn failed_targets(builds: &[TargetBuild]) -> Vec<&str> {
+    builds
+        .iter()
+        .filter(|b| b.result.is_err())
+        .map(|b| b.target.as_str())
+        .collect()
+}
+
+// Size/status table of a multi-target build, one row per artifact and one
+// per failed target
+fn matrix_lines(builds: &[TargetBuild]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut lines = vec![format!(
+        "{:<36} {:<24} {:>12}  Status",
+        "Target", "Artifact", "Size"
+    )];
+    for build in builds {
+        match &build.result {
+            Ok(artifacts) => {
+                for artifact in artifacts {
+                    let size_kb = artifact.path.metadata()?.len() as f64 / 1024.0;
+                    lines.push(format!(
+                        "{:<36} {:<24} {:>9.1} KB  ok",
+                        artifact.target,
+                        artifact.file_name(),
+                        size_kb
+                    ));
+                }
+            }
+            Err(_) => lines.push(format!(
+                "{:<36} {:<24} {:>12}  FAILED",
+                build.target, "-", "-"
+            )),
+        }
+    }
+    Ok(lines)
+}
+
+fn artifact_sizes(artifacts: &[Artifact]) -> Result<BTreeMap<String, u64>, Box<dyn Error>> {
+    let mut sizes = BTreeMap::new();
+    for artifact in artifacts {
+        sizes.insert(artifact.file_name(), artifact.path.metadata()?.len());
+    }
+    Ok(sizes)
+}
+
+fn size_delta(baseline: &HistoryEntry, name: &str, size: u64) -> String {
+    let Some(&previous) = baseline.sizes.get(name) else {
+        return format!("new since {}", baseline.label());
+    };
+    let diff = size as f64 - previous as f64;
+    let percent = if previous > 0 {
+        diff * 100.0 / previous as f64
+    } else {
+        0.0
+    };
+    format!(
+        "{:+.1} KB ({percent:+.1}%) vs {}",
+        diff / 1024.0,
+        baseline.label()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn build_system(mode: BuildMode) -> BuildSystem {
+        let panic = crate::build_std::PanicStrategy::default_for(mode);
+        BuildSystem {
+            options: BuildOptions {
+                mode,
+                build_std: BuildStdOptions {
+                    crates: None,
+                    features: Vec::new(),
+                    panic,
+                },
+                targets: vec!["x86_64-unknown-linux-gnu".to_string()],
+                keep_going: false,
+                use_upx: false,
+                upx_args: Vec::new(),
+                clean: false,
+                profile: "rbt".to_string(),
+                // Leaves the environment to the profile settings under test
+                profile_mode: ProfileMode::Manifest,
+                profile_settings: Vec::new(),
+                rustflags: Vec::new(),
+                packages: Vec::new(),
+                bins: Vec::new(),
+                all_bins: false,
+                examples: Vec::new(),
+                features: FeatureSelection::default(),
+                dry_run: true,
+                analyze: false,
+                report: false,
+                report_file: None,
+                history_file: PathBuf::from("/ws/target/rbt-size-history.json"),
+                baseline: None,
+                size_limits: SizeLimits {
+                    max_size: None,
+                    max_growth: None,
+                },
+                checksums: Vec::new(),
+                static_linking: None,
+                hardening: HardeningConfig::default(),
+            },
+            workspace: Workspace {
+                root: PathBuf::from("/ws"),
+                target_directory: PathBuf::from("/ws/target"),
+                members: vec![WorkspaceMember {
+                    name: "app".to_string(),
+                    version: "0.1.0".to_string(),
+                    description: None,
+                    license: None,
+                    authors: Vec::new(),
+                    homepage: None,
+                    repository: None,
+                    manifest_path: PathBuf::from("/ws/app/Cargo.toml"),
+                    bin_targets: vec!["app".to_string(), "app-tool".to_string()],
+                    example_targets: vec!["demo".to_string()],
+                }],
+            },
+            steps: RefCell::new(Vec::new()),
+            builds: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn expected(system: &BuildSystem, target: &str) -> Vec<PathBuf> {
+        let members = system.built_members().unwrap();
+        system.expected_executables(target, &members)
+    }
+
+    #[test]
+    fn every_binary_is_expected_without_a_selection() {
+        let system = build_system(BuildMode::Stable);
+        let dir = PathBuf::from("/ws/target/x86_64-unknown-linux-gnu/rbt");
+        assert_eq!(
+            expected(&system, "x86_64-unknown-linux-gnu"),
+            [dir.join("app"), dir.join("app-tool")]
+        );
+        assert_eq!(
+            expected(&system, "x86_64-pc-windows-msvc"),
+            [
+                PathBuf::from("/ws/target/x86_64-pc-windows-msvc/rbt/app.exe"),
+                PathBuf::from("/ws/target/x86_64-pc-windows-msvc/rbt/app-tool.exe")
+            ]
+        );
+    }
+
+    #[test]
+    fn bins_and_examples_narrow_the_build() {
+        let mut system = build_system(BuildMode::Stable);
+        system.options.bins = vec!["app-tool".to_string()];
+        system.options.examples = vec!["demo".to_string()];
+        let dir = PathBuf::from("/ws/target/x86_64-unknown-linux-gnu/rbt");
+        assert_eq!(
+            expected(&system, "x86_64-unknown-linux-gnu"),
+            [dir.join("app-tool"), dir.join("examples/demo")]
+        );
+        let members = system.built_members().unwrap();
+        let args = args(
+            &system
+                .build_command(
+                    "x86_64-unknown-linux-gnu",
+                    &members,
+                    UserRustflags::default(),
+                )
+                .unwrap(),
+        );
+        assert!(args.ends_with(&[
+            "--bin".to_string(),
+            "app-tool".to_string(),
+            "--example".to_string(),
+            "demo".to_string()
+        ]));
+
+        // `--bins` brings back every binary next to the example
+        system.options.bins.clear();
+        system.options.all_bins = true;
+        assert_eq!(
+            expected(&system, "x86_64-unknown-linux-gnu"),
+            [
+                dir.join("app"),
+                dir.join("app-tool"),
+                dir.join("examples/demo")
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_going_records_failures_and_continues() {
+        let dir = std::env::temp_dir().join(format!("rbt-matrix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app");
+        fs::write(&path, vec![0; 2048]).unwrap();
+        let artifact = Artifact {
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            package: "app".to_string(),
+            name: "app".to_string(),
+            kind: ArtifactKind::Executable,
+            path,
+            size_before_upx: None,
+            hardening: None,
+            hardening_before_upx: None,
+        };
+
+        let mut system = build_system(BuildMode::Stable);
+        system.options.keep_going = true;
+        system
+            .record_build("aarch64-unknown-linux-gnu", Err("linker not found".into()))
+            .unwrap();
+        system
+            .record_build("x86_64-unknown-linux-gnu", Ok(vec![artifact]))
+            .unwrap();
+
+        let builds = system.builds.borrow();
+        assert_eq!(failed_targets(&builds), ["aarch64-unknown-linux-gnu"]);
+        assert_eq!(builds[0].result.as_ref().unwrap_err(), "linker not found");
+        let lines = matrix_lines(&builds).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("aarch64-unknown-linux-gnu"));
+        assert!(lines[1].ends_with("  FAILED"));
+        assert!(lines[2].contains(" app "));
+        assert!(lines[2].ends_with("2.0 KB  ok"));
+        drop(builds);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Without --keep-going the first failure ends the run
+        system.options.keep_going = false;
+        assert!(
+            system
+                .record_build("armv7-unknown-linux-gnueabihf", Err("failed".into()))
+                .is_err()
+        );
+        assert_eq!(system.builds.borrow().len(), 3);
+    }
+
+    #[test]
+    fn stable_mode_drops_nightly_only_arguments() {
+        let dir = std::env::temp_dir().join(format!("rbt-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+
+        for mode in [BuildMode::Nightly, BuildMode::Stable] {
+            let mut system = build_system(mode);
+            let panic = system.options.build_std.panic;
+            system.workspace.root = dir.clone();
+            system.options.profile_mode = ProfileMode::Environment;
+            system.options.profile_settings = crate::project_config::ProjectConfig::default()
+                .profile_settings(cargo_config::DEFAULT_PRESET, panic)
+                .unwrap();
+            let members = system.built_members().unwrap();
+            let user_flags = UserRustflags {
+                flags: vec!["-Ctarget-cpu=native".to_string()],
+                source: "RUSTFLAGS".to_string(),
+            };
+            let cmd = system
+                .build_command("x86_64-unknown-linux-gnu", &members, user_flags)
+                .unwrap();
+            let args = args(&cmd);
+            let env = |name: &str| {
+                cmd.get_envs()
+                    .find(|(key, _)| *key == name)
+                    .and_then(|(_, value)| value)
+                    .map(|value| value.to_string_lossy().into_owned())
+            };
+            let rustflags = env("CARGO_ENCODED_RUSTFLAGS").unwrap();
+            assert!(rustflags.starts_with("-Ctarget-cpu=native"));
+
+            let nightly = mode == BuildMode::Nightly;
+            assert_eq!(args[0], mode.toolchain());
+            assert_eq!(
+                args.contains(&"build-std=std,panic_abort".to_string()),
+                nightly
+            );
+            assert_eq!(args.iter().any(|arg| arg == "-Z"), nightly);
+            assert_eq!(rustflags.contains("-Zunstable-options"), nightly);
+            assert_eq!(rustflags.contains("-Cpanic=immediate-abort"), nightly);
+            // Cargo only knows `abort` in the profile, in either mode
+            assert_eq!(env("CARGO_PROFILE_RBT_PANIC").as_deref(), Some("abort"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,7 +1,20 @@
 use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
 
-const RELEASE_PROFILE_SETTINGS: [(&str, &str); 5] = [
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::dependency_checker;
+use crate::dry_run;
+
+pub const DEFAULT_PRESET: &str = "size";
+pub const DEFAULT_PROFILE: &str = "rbt";
+pub const RELEASE_PROFILE: &str = "release";
+// Cargo's built-in profiles; any other name is a custom profile inheriting from release
+const BUILTIN_PROFILES: [&str; 5] = ["dev", "release", "test", "bench", "doc"];
+
+const SIZE_PRESET: &[(&str, &str)] = &[
     ("opt-level", "\'z\'"),
     ("lto", "true"),
     ("codegen-units", "1"),
@@ -9,99 +22,541 @@ const RELEASE_PROFILE_SETTINGS: [(&str, &str); 5] = [
     ("strip", "true"),
 ];
 
+const SPEED_PRESET: &[(&str, &str)] = &[
+    ("opt-level", "3"),
+    ("lto", "\'fat\'"),
+    ("codegen-units", "1"),
+    ("panic", "\'abort\'"),
+    ("strip", "true"),
+];
+
+const BALANCED_PRESET: &[(&str, &str)] = &[
+    ("opt-level", "2"),
+    ("lto", "\'thin\'"),
+    ("codegen-units", "4"),
+    ("panic", "\'abort\'"),
+    ("strip", "true"),
+];
+
+const DEBUGGABLE_RELEASE_PRESET: &[(&str, &str)] = &[
+    ("opt-level", "2"),
+    ("lto", "\'thin\'"),
+    ("codegen-units", "16"),
+    ("panic", "\'abort\'"),
+    ("strip", "false"),
+    ("debug", "true"),
+];
+
+pub const BUILTIN_PRESETS: [(&str, &[(&str, &str)]); 4] = [
+    ("size", SIZE_PRESET),
+    ("speed", SPEED_PRESET),
+    ("balanced", BALANCED_PRESET),
+    ("debuggable-release", DEBUGGABLE_RELEASE_PRESET),
+];
+
+const BACKUP_SUFFIX: &str = ".rbt-backup";
+
+// Manifests currently rewritten by a live manager, restored by the signal handler
+static PENDING_RESTORES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static SIGNAL_HANDLER: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileMode {
+    Environment, // 通过 CARGO_PROFILE_RELEASE_* 环境变量传递
+    Manifest,    // 直接改写 Cargo.toml
+}
+
+// Profile keys paired with their value written as a TOML literal
+pub type ProfileSettings = Vec<(String, String)>;
+
+pub fn builtin_preset(name: &str) -> Option<ProfileSettings> {
+    BUILTIN_PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, settings)| {
+            settings
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+}
+
+// Replaces existing keys in place and appends new ones
+pub fn override_settings(settings: &mut ProfileSettings, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        let literal = value.to_string();
+        match settings.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = literal,
+            None => settings.push((key.clone(), literal)),
+        }
+    }
+}
+
+// The built-in profiles other than `release` cannot host the optimised build
+pub fn validate_profile_name(profile: &str) -> Result<(), Box<dyn Error>> {
+    if profile != RELEASE_PROFILE && BUILTIN_PROFILES.contains(&profile) {
+        return Err(format!(
+            "Cannot build with the `{profile}` profile; use `release` or a custom profile name"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// A custom profile starts from `inherits = "release"` so plain
+// `cargo build --release` keeps producing what the manifest says
+fn settings_for_profile(profile: &str, settings: &[(String, String)]) -> ProfileSettings {
+    let mut result = Vec::new();
+    if profile != RELEASE_PROFILE {
+        result.push(("inherits".to_string(), format!("'{RELEASE_PROFILE}'")));
+    }
+    result.extend(settings.iter().cloned());
+    result
+}
+
+fn manifest_profile(cargo_toml: &Path, name: &str) -> Result<toml::Table, Box<dyn Error>> {
+    let content = fs::read_to_string(cargo_toml)?;
+    let mut manifest: toml::Table = content.parse()?;
+    let profile = manifest
+        .remove("profile")
+        .and_then(|mut p| p.as_table_mut()?.remove(name));
+    match profile {
+        Some(toml::Value::Table(table)) => Ok(table),
+        _ => Ok(toml::Table::new()),
+    }
+}
+
+// Converts a TOML literal into the plain form cargo expects in environment variables
+fn literal_to_env_value(key: &str, literal: &str) -> Result<String, Box<dyn Error>> {
+    match literal.parse::<Value>()? {
+        Value::String(s) => Ok(s.into_value()),
+        Value::Integer(i) => Ok(i.into_value().to_string()),
+        Value::Float(f) => Ok(f.into_value().to_string()),
+        Value::Boolean(b) => Ok(b.into_value().to_string()),
+        _ => Err(format!(
+            "Profile setting `{key}` cannot be passed through the environment; use --edit-manifest"
+        )
+        .into()),
+    }
+}
+
+// The value cargo will end up using for a profile key
+pub fn effective_profile_setting(
+    cargo_toml: &Path,
+    profile: &str,
+    settings: &[(String, String)],
+    key: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(value) = manifest_profile(cargo_toml, profile)?.get(key) {
+        return Ok(Some(match value {
+            toml::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }));
+    }
+    settings
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(k, literal)| literal_to_env_value(k, literal))
+        .transpose()
+}
+
+// Settings already present in the manifest win, mirroring the manifest-editing path
+pub fn profile_env(
+    cargo_toml: &Path,
+    profile: &str,
+    settings: &[(String, String)],
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let existing = manifest_profile(cargo_toml, profile)?;
+    let prefix = format!(
+        "CARGO_PROFILE_{}_",
+        profile.to_uppercase().replace('-', "_")
+    );
+
+    let mut envs = Vec::new();
+    for (key, value) in settings_for_profile(profile, settings) {
+        if existing.contains_key(&key) {
+            continue;
+        }
+        envs.push((
+            format!("{prefix}{}", key.to_uppercase().replace('-', "_")),
+            literal_to_env_value(&key, &value)?,
+        ));
+    }
+    Ok(envs)
+}
+
+// The change `CargoConfigManager::ensure_profile` would make, as a unified diff
+pub fn profile_manifest_diff(
+    cargo_toml: &Path,
+    profile: &str,
+    settings: &[(String, String)],
+) -> Result<Option<String>, Box<dyn Error>> {
+    let content = fs::read_to_string(cargo_toml)?;
+    let updated = apply_profile(&content, profile, settings)?;
+    if updated == content {
+        return Ok(None);
+    }
+    Ok(Some(dry_run::unified_diff(
+        "Cargo.toml",
+        &content,
+        &updated,
+    )))
+}
+
 pub struct CargoConfigManager {
-    cargo_toml: String,
+    cargo_toml: PathBuf,
+    backup_path: PathBuf,
     original_content: String,
+    modified: bool,
 }
 
 impl CargoConfigManager {
-    pub fn new(cargo_toml: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn new(cargo_toml: &Path) -> Result<Self, Box<dyn Error>> {
+        let backup_path = backup_path_for(cargo_toml);
+        if backup_path.exists() {
+            Self::recover_stale_backup(cargo_toml, &backup_path)?;
+        }
+
         let content = fs::read_to_string(cargo_toml)?;
         Ok(Self {
-            cargo_toml: cargo_toml.to_string(),
-            original_content: content.clone(),
+            cargo_toml: cargo_toml.to_path_buf(),
+            backup_path,
+            original_content: content,
+            modified: false,
         })
     }
 
-    pub fn ensure_release_profile(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut lines: Vec<String> = self
-            .original_content
-            .lines()
-            .map(|s| s.to_string())
-            .collect();
-        self.add_missing_section(&mut lines)?;
-        self.update_profile_settings(&mut lines)?;
-        self.write_file(&lines)?;
+    fn recover_stale_backup(cargo_toml: &Path, backup_path: &Path) -> Result<(), Box<dyn Error>> {
+        println!(
+            "Found a backup of {} left by an interrupted build: {}",
+            cargo_toml.display(),
+            backup_path.display()
+        );
+        if !dependency_checker::get_confirmation("Restore Cargo.toml from this backup?") {
+            return Err(format!(
+                "Refusing to continue while a stale backup exists; restore or delete {} first",
+                backup_path.display()
+            )
+            .into());
+        }
+
+        restore_from_backup(cargo_toml, backup_path)?;
+        println!("Restored {} from backup", cargo_toml.display());
         Ok(())
     }
 
-    fn add_missing_section(&self, lines: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
-        if !self.original_content.contains("[profile.release]") {
-            lines.push("[profile.release]".to_string());
+    pub fn ensure_profile(
+        &mut self,
+        profile: &str,
+        settings: &[(String, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let updated = apply_profile(&self.original_content, profile, settings)?;
+        if updated == self.original_content {
+            return Ok(());
         }
+        self.write_backup()?;
+        self.write_file(&updated)?;
         Ok(())
     }
 
-    fn update_profile_settings(&self, lines: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
-        let section_start = self.find_section_index(lines)?;
-        let existing_keys = self.parse_existing_settings(lines, section_start)?;
+    // The backup must hit the disk before the manifest is touched
+    fn write_backup(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.backup_path, &self.original_content)
+            .map_err(|e| format!("Failed to write {}: {e}", self.backup_path.display()))?;
+        install_signal_handler()?;
+        register_pending_restore(&self.cargo_toml);
+        self.modified = true;
+        Ok(())
+    }
 
-        for (key, value) in RELEASE_PROFILE_SETTINGS.iter() {
-            if !existing_keys.contains(&key.to_string()) {
-                let insert_pos = self.find_insert_position(lines, section_start)?;
-                lines.insert(insert_pos, format!("{key} = {value}"));
-            }
-        }
+    fn write_file(&self, content: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.cargo_toml, content)?;
+        Ok(())
+    }
 
+    pub fn restore(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.modified {
+            return Ok(());
+        }
+        fs::write(&self.cargo_toml, &self.original_content)?;
+        if self.backup_path.exists() {
+            fs::remove_file(&self.backup_path)?;
+        }
+        unregister_pending_restore(&self.cargo_toml);
+        self.modified = false;
         Ok(())
     }
+}
 
-    fn find_section_index(&self, lines: &[String]) -> Result<usize, Box<dyn Error>> {
-        for (i, line) in lines.iter().enumerate() {
-            if line.trim() == "[profile.release]" {
-                return Ok(i);
-            }
+impl Drop for CargoConfigManager {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            eprintln!(
+                "Failed to restore {}: {e} (backup kept at {})",
+                self.cargo_toml.display(),
+                self.backup_path.display()
+            );
         }
-        Ok(lines.len() - 1)
-    }
-
-    fn parse_existing_settings(
-        &self,
-        lines: &[String],
-        start: usize,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
-        let mut existing = Vec::new();
-        for line in lines.iter().skip(start + 1) {
-            if line.trim().is_empty() || line.trim().starts_with('[') {
-                break;
-            }
-            if let Some(key) = line.split('=').next() {
-                existing.push(key.trim().to_string());
+    }
+}
+
+// Adds the missing profile keys while leaving comments, ordering and the
+// user's own values untouched. `[profile.release]` headers, inline tables
+// and dotted keys such as `profile.release.lto = "fat"` are all recognised.
+fn apply_profile(
+    content: &str,
+    name: &str,
+    settings: &[(String, String)],
+) -> Result<String, Box<dyn Error>> {
+    let mut doc: DocumentMut = content
+        .parse()
+        .map_err(|e| format!("Failed to parse Cargo.toml: {e}"))?;
+
+    let profile = doc.entry("profile").or_insert_with(|| {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    let profile_is_inline = profile.is_inline_table();
+    let profile = profile
+        .as_table_like_mut()
+        .ok_or("`profile` in Cargo.toml is not a table")?;
+
+    if !profile.contains_key(name) {
+        let table = if profile_is_inline {
+            Item::Value(Value::InlineTable(InlineTable::new()))
+        } else {
+            let mut table = Table::new();
+            if !content.is_empty() && !content.ends_with("\n\n") {
+                table.decor_mut().set_prefix("\n");
             }
+            Item::Table(table)
+        };
+        profile.insert(name, table);
+    }
+    let table = profile
+        .get_mut(name)
+        .and_then(Item::as_table_like_mut)
+        .ok_or_else(|| format!("`profile.{name}` in Cargo.toml is not a table"))?;
+
+    for (key, value) in settings_for_profile(name, settings) {
+        if !table.contains_key(&key) {
+            let value: Value = value.parse()?;
+            table.insert(&key, Item::Value(value));
         }
-        Ok(existing)
     }
 
-    fn find_insert_position(
-        &self,
-        lines: &[String],
-        start: usize,
-    ) -> Result<usize, Box<dyn Error>> {
-        for (i, line) in lines.iter().enumerate().skip(start + 1) {
-            if line.trim().is_empty() || line.trim().starts_with('[') {
-                return Ok(i);
+    let mut updated = doc.to_string();
+    if content.ends_with('\n') && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    Ok(updated)
+}
+
+fn backup_path_for(cargo_toml: &Path) -> PathBuf {
+    let mut name = cargo_toml.as_os_str().to_owned();
+    name.push(BACKUP_SUFFIX);
+    PathBuf::from(name)
+}
+
+fn restore_from_backup(cargo_toml: &Path, backup_path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::copy(backup_path, cargo_toml)?;
+    fs::remove_file(backup_path)?;
+    Ok(())
+}
+
+fn register_pending_restore(cargo_toml: &Path) {
+    let mut pending = PENDING_RESTORES.lock().unwrap_or_else(|e| e.into_inner());
+    if !pending.iter().any(|p| p == cargo_toml) {
+        pending.push(cargo_toml.to_path_buf());
+    }
+}
+
+fn unregister_pending_restore(cargo_toml: &Path) {
+    let mut pending = PENDING_RESTORES.lock().unwrap_or_else(|e| e.into_inner());
+    pending.retain(|p| p != cargo_toml);
+}
+
+// Drop guards do not run when the process is killed by SIGINT/SIGTERM,
+// so the handler restores every pending manifest itself before exiting.
+fn install_signal_handler() -> Result<(), Box<dyn Error>> {
+    let mut result = Ok(());
+    SIGNAL_HANDLER.call_once(|| {
+        result = ctrlc::set_handler(|| {
+            let pending = PENDING_RESTORES.lock().unwrap_or_else(|e| e.into_inner());
+            for cargo_toml in pending.iter() {
+                let backup_path = backup_path_for(cargo_toml);
+                match restore_from_backup(cargo_toml, &backup_path) {
+                    Ok(()) => eprintln!("\nInterrupted, restored {}", cargo_toml.display()),
+                    Err(e) => eprintln!(
+                        "\nInterrupted, failed to restore {}: {e} (backup kept at {})",
+                        cargo_toml.display(),
+                        backup_path.display()
+                    ),
+                }
             }
-        }
-        Ok(lines.len())
+            std::process::exit(130);
+        });
+    });
+    result.map_err(|e| format!("Failed to install signal handler: {e}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEFAULT_PRESET, builtin_preset};
+
+    fn apply_release_profile(content: &str) -> Result<String, Box<dyn std::error::Error>> {
+        super::apply_profile(
+            content,
+            super::RELEASE_PROFILE,
+            &builtin_preset(DEFAULT_PRESET).unwrap(),
+        )
     }
 
-    fn write_file(&self, lines: &[String]) -> Result<(), Box<dyn Error>> {
-        fs::write(&self.cargo_toml, lines.join("\n"))?;
-        Ok(())
+    const FULL_PROFILE: &str = "\
+opt-level = 'z'
+lto = true
+codegen-units = 1
+panic = 'abort'
+strip = true
+";
+
+    #[test]
+    fn appends_release_section_when_missing() {
+        let manifest = "[package]\nname = \"demo\"\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert_eq!(
+            updated,
+            format!("{manifest}\n[profile.release]\n{FULL_PROFILE}")
+        );
     }
 
-    pub fn restore(&self) -> Result<(), Box<dyn Error>> {
-        fs::write(&self.cargo_toml, &self.original_content)?;
-        Ok(())
+    #[test]
+    fn custom_profile_inherits_from_release() {
+        let manifest = "[profile.release]\nopt-level = 3\n";
+        let updated =
+            super::apply_profile(manifest, "rbt", &builtin_preset(DEFAULT_PRESET).unwrap())
+                .unwrap();
+        assert_eq!(
+            updated,
+            format!("{manifest}\n[profile.rbt]\ninherits = 'release'\n{FULL_PROFILE}")
+        );
+    }
+
+    #[test]
+    fn keeps_trailing_newline_state() {
+        let manifest = "[package]\nname = \"demo\"";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert_eq!(
+            updated,
+            format!("{manifest}\n\n[profile.release]\n{FULL_PROFILE}")
+        );
+    }
+
+    #[test]
+    fn existing_keys_past_blank_lines_and_comments_are_respected() {
+        let manifest = "\
+[package]
+name = \"demo\"
+
+[profile.release]
+# we need unwinding in production
+panic = \"unwind\"
+
+opt-level = 3 # speed matters here
+
+[dependencies]
+serde = \"1\"
+";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert_eq!(
+            updated,
+            "\
+[package]
+name = \"demo\"
+
+[profile.release]
+# we need unwinding in production
+panic = \"unwind\"
+
+opt-level = 3 # speed matters here
+lto = true
+codegen-units = 1
+strip = true
+
+[dependencies]
+serde = \"1\"
+"
+        );
+    }
+
+    #[test]
+    fn unusual_header_spacing_is_recognised() {
+        let manifest = "[ profile . release ]   # tuned\nopt-level = 3\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert_eq!(
+            updated,
+            "[ profile . release ]   # tuned\nopt-level = 3\nlto = true\ncodegen-units = 1\npanic = 'abort'\nstrip = true\n"
+        );
+    }
+
+    #[test]
+    fn root_dotted_keys_stay_dotted() {
+        let manifest = "profile.release.lto = \"fat\"\n\n[package]\nname = \"demo\"\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert_eq!(
+            updated,
+            "\
+profile.release.lto = \"fat\"
+profile.release.opt-level = 'z'
+profile.release.codegen-units = 1
+profile.release.panic = 'abort'
+profile.release.strip = true
+
+[package]
+name = \"demo\"
+"
+        );
+    }
+
+    #[test]
+    fn dotted_keys_inside_profile_table() {
+        let manifest = "[profile]\nrelease.strip = false\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        assert!(updated.starts_with("[profile]\nrelease.strip = false\nrelease.opt-level = 'z'\n"));
+        assert!(!updated.contains("strip = true"));
+    }
+
+    #[test]
+    fn inline_release_table_is_extended_in_place() {
+        let manifest = "[profile]\nrelease = { lto = \"thin\" }\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        let parsed: toml::Value = updated.parse().unwrap();
+        let release = &parsed["profile"]["release"];
+        assert_eq!(release["lto"].as_str(), Some("thin"));
+        assert_eq!(release["opt-level"].as_str(), Some("z"));
+        assert!(!updated.contains("[profile.release]"));
+    }
+
+    #[test]
+    fn release_subtables_do_not_hide_missing_keys() {
+        let manifest = "[profile.release.package.foo]\nopt-level = 3\n";
+        let updated = apply_release_profile(manifest).unwrap();
+        let parsed: toml::Value = updated.parse().unwrap();
+        let release = &parsed["profile"]["release"];
+        assert_eq!(release["opt-level"].as_str(), Some("z"));
+        assert_eq!(release["package"]["foo"]["opt-level"].as_integer(), Some(3));
+    }
+
+    #[test]
+    fn complete_profile_is_left_byte_for_byte() {
+        let manifest = format!("# top comment\n[profile.release]\n{FULL_PROFILE}\n# trailing\n");
+        assert_eq!(apply_release_profile(&manifest).unwrap(), manifest);
+    }
+
+    #[test]
+    fn non_table_profile_is_rejected() {
+        assert!(apply_release_profile("profile = 1\n").is_err());
+        assert!(apply_release_profile("[profile]\nrelease = \"fast\"\n").is_err());
     }
 }
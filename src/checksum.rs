@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn manifest_name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "SHA256SUMS",
+            Algorithm::Sha512 => "SHA512SUMS",
+        }
+    }
+
+    // Recognised by digest length, so manifests may have any file name
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            64 => Some(Algorithm::Sha256),
+            128 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+pub fn file_digest(path: &Path, algorithm: Algorithm) -> Result<String, Box<dyn Error>> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    Ok(match algorithm {
+        Algorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(&mut file, &mut hasher)?;
+            format!("{:x}", hasher.finalize())
+        }
+        Algorithm::Sha512 => {
+            let mut hasher = Sha512::new();
+            io::copy(&mut file, &mut hasher)?;
+            format!("{:x}", hasher.finalize())
+        }
+    })
+}
+
+// Writes one `sha256sum`-compatible manifest per directory and algorithm,
+// listing the given files by name. Entries of an existing manifest are kept
+// while their file is still there, so other targets and earlier runs sharing
+// the directory stay listed. Returns the manifests written.
+pub fn write_manifests(
+    files: &[PathBuf],
+    algorithms: &[Algorithm],
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut by_dir: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for file in files {
+        let dir = file.parent().unwrap_or(Path::new("."));
+        by_dir.entry(dir).or_default().push(file);
+    }
+
+    let mut written = Vec::new();
+    for (dir, files) in by_dir {
+        for &algorithm in algorithms {
+            let path = dir.join(algorithm.manifest_name());
+            let mut digests = BTreeMap::new();
+            if path.is_file() {
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+                for entry in
+                    parse_manifest(&content).map_err(|e| format!("{}: {e}", path.display()))?
+                {
+                    if dir.join(&entry.file_name).is_file() {
+                        digests.insert(entry.file_name, entry.digest);
+                    }
+                }
+            }
+            for file in &files {
+                let name = file
+                    .file_name()
+                    .ok_or_else(|| format!("Invalid artifact path {}", file.display()))?;
+                digests.insert(
+                    name.to_string_lossy().into_owned(),
+                    file_digest(file, algorithm)?,
+                );
+            }
+            let manifest: String = digests
+                .iter()
+                .map(|(name, digest)| format!("{digest}  {name}\n"))
+                .collect();
+            fs::write(&path, manifest)
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+pub struct ManifestEntry {
+    pub digest: String,
+    pub file_name: String,
+}
+
+// `<hex>  <name>`, or `<hex> *<name>` as written by `sha256sum --binary`
+pub fn parse_manifest(content: &str) -> Result<Vec<ManifestEntry>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once(' ').and_then(|(digest, rest)| {
+            let name = rest.strip_prefix([' ', '*'])?;
+            let valid = Algorithm::from_hex_len(digest.len()).is_some()
+                && digest.chars().all(|c| c.is_ascii_hexdigit());
+            (valid && !name.is_empty()).then(|| ManifestEntry {
+                digest: digest.to_ascii_lowercase(),
+                file_name: name.to_string(),
+            })
+        });
+        entries.push(parsed.ok_or_else(|| format!("Malformed checksum line {}", number + 1))?);
+    }
+    Ok(entries)
+}
+
+pub struct VerifyResult {
+    pub file_name: String,
+    pub status: VerifyStatus,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyStatus {
+    Ok,
+    Mismatch,
+    Missing,
+}
+
+// Checks every entry of `manifest` against the files in `dir`
+pub fn verify(dir: &Path, manifest: &Path) -> Result<Vec<VerifyResult>, Box<dyn Error>> {
+    let content = fs::read_to_string(manifest)
+        .map_err(|e| format!("Failed to read {}: {e}", manifest.display()))?;
+    let entries = parse_manifest(&content).map_err(|e| format!("{}: {e}", manifest.display()))?;
+
+    let mut results = Vec::new();
+    for entry in entries {
+        let path = dir.join(&entry.file_name);
+        let status = if !path.is_file() {
+            VerifyStatus::Missing
+        } else {
+            let algorithm =
+                Algorithm::from_hex_len(entry.digest.len()).ok_or("Unsupported digest length")?;
+            if file_digest(&path, algorithm)? == entry.digest {
+                VerifyStatus::Ok
+            } else {
+                VerifyStatus::Mismatch
+            }
+        };
+        results.push(VerifyResult {
+            file_name: entry.file_name,
+            status,
+        });
+    }
+    Ok(results)
+}
+
+// `verify`: checks `dir` against the given manifest, or every standard one in it
+pub fn verify_dir(dir: &Path, manifest: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let manifests: Vec<PathBuf> = match manifest {
+        Some(manifest) => vec![manifest.to_path_buf()],
+        None => [Algorithm::Sha256, Algorithm::Sha512]
+            .iter()
+            .map(|algorithm| dir.join(algorithm.manifest_name()))
+            .filter(|path| path.is_file())
+            .collect(),
+    };
+    if manifests.is_empty() {
+        return Err(format!("No SHA256SUMS or SHA512SUMS found in {}", dir.display()).into());
+    }
+
+    let mut failed = 0;
+    for manifest in &manifests {
+        println!("Verifying against {}", manifest.display());
+        for result in verify(dir, manifest)? {
+            let status = match result.status {
+                VerifyStatus::Ok => "OK",
+                VerifyStatus::Mismatch => "FAILED",
+                VerifyStatus::Missing => "MISSING",
+            };
+            if result.status != VerifyStatus::Ok {
+                failed += 1;
+            }
+            println!("  {}: {status}", result.file_name);
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "Checksum verification failed: {failed} mismatched or missing entries"
+        )
+        .into());
+    }
+    println!("All checksums match");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_round_trip_through_verify() {
+        let dir = std::env::temp_dir().join(format!("rbt-checksum-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let app = dir.join("app");
+        fs::write(&app, b"binary").unwrap();
+
+        let manifests = write_manifests(
+            std::slice::from_ref(&app),
+            &[Algorithm::Sha256, Algorithm::Sha512],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&manifests[0]).unwrap(),
+            "9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd  app\n"
+        );
+        for manifest in &manifests {
+            let results = verify(&dir, manifest).unwrap();
+            assert_eq!(results[0].status, VerifyStatus::Ok);
+        }
+
+        fs::write(&app, b"tampered").unwrap();
+        assert_eq!(
+            verify(&dir, &manifests[0]).unwrap()[0].status,
+            VerifyStatus::Mismatch
+        );
+        fs::remove_file(&app).unwrap();
+        assert_eq!(
+            verify(&dir, &manifests[1]).unwrap()[0].status,
+            VerifyStatus::Missing
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifests_merge_with_earlier_runs() {
+        let dir = std::env::temp_dir().join(format!("rbt-checksum-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let [app, lib, old] = ["app.tar.gz", "lib.zip", "old.zip"].map(|name| dir.join(name));
+        fs::write(&app, b"first").unwrap();
+        fs::write(&lib, b"library").unwrap();
+        fs::write(&old, b"old").unwrap();
+        write_manifests(&[app.clone(), old.clone()], &[Algorithm::Sha256]).unwrap();
+
+        fs::write(&app, b"second").unwrap();
+        fs::remove_file(&old).unwrap();
+        let manifests = write_manifests(std::slice::from_ref(&lib), &[Algorithm::Sha256]).unwrap();
+        let entries = parse_manifest(&fs::read_to_string(&manifests[0]).unwrap()).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.file_name.as_str()).collect();
+        assert_eq!(names, ["app.tar.gz", "lib.zip"]);
+        // Untouched entries keep their digest; app.tar.gz was not rewritten here
+        assert_eq!(entries[0].digest, format!("{:x}", Sha256::digest(b"first")));
+
+        write_manifests(std::slice::from_ref(&app), &[Algorithm::Sha256]).unwrap();
+        let results = verify(&dir, &manifests[0]).unwrap();
+        assert!(results.iter().all(|r| r.status == VerifyStatus::Ok));
+        assert_eq!(results.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn binary_mode_lines_are_accepted() {
+        let digest = "a".repeat(64);
+        let entries = parse_manifest(&format!("{digest} *app.exe\n\n")).unwrap();
+        assert_eq!(entries[0].file_name, "app.exe");
+        assert!(parse_manifest("abc  app\n").is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::package::{self, Asset, PackageInput, TreeEntry};
+use crate::workspace::WorkspaceMember;
+
+// `[deb]` section of the project configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DebConfig {
+    // Defaults to the first of the package's `authors`
+    pub maintainer: Option<String>,
+    pub revision: String,
+    pub section: String,
+    pub priority: String,
+    pub depends: Vec<String>,
+    pub assets: Vec<Asset>,
+    // Installed to /lib/systemd/system, relative to the workspace root
+    pub systemd_units: Vec<PathBuf>,
+    // Enable and start the units on install
+    pub systemd_enable: bool,
+}
+
+impl Default for DebConfig {
+    fn default() -> Self {
+        Self {
+            maintainer: None,
+            revision: "1".to_string(),
+            section: "utils".to_string(),
+            priority: "optional".to_string(),
+            depends: Vec::new(),
+            assets: Vec::new(),
+            systemd_units: Vec::new(),
+            systemd_enable: true,
+        }
+    }
+}
+
+const UNIT_DIR: &str = "lib/systemd/system";
+
+// `<package>_<version>-<revision>_<arch>.deb`, as dpkg names them
+pub fn file_name(
+    member: &WorkspaceMember,
+    target: &str,
+    config: &DebConfig,
+) -> Result<String, Box<dyn Error>> {
+    Ok(format!(
+        "{}_{}_{}.deb",
+        package_name(&member.name),
+        version(&member.version, &config.revision),
+        architecture(target)?
+    ))
+}
+
+// Debian architecture of a Linux target triple
+pub fn architecture(target: &str) -> Result<&'static str, String> {
+    let unsupported = || format!("No Debian architecture for target `{target}`");
+    if !target.contains("-linux") {
+        return Err(unsupported());
+    }
+    let arch = target.split('-').next().unwrap_or_default();
+    Ok(match arch {
+        "x86_64" => "amd64",
+        "i386" | "i586" | "i686" => "i386",
+        "aarch64" => "arm64",
+        "arm" | "armv5te" | "armv6" | "armv7" | "thumbv7neon" => {
+            if target.ends_with("hf") {
+                "armhf"
+            } else {
+                "armel"
+            }
+        }
+        "powerpc" => "powerpc",
+        "powerpc64" => "ppc64",
+        "powerpc64le" => "ppc64el",
+        "riscv64gc" => "riscv64",
+        "s390x" => "s390x",
+        "mips" => "mips",
+        "mipsel" => "mipsel",
+        "mips64el" => "mips64el",
+        "loongarch64" => "loong64",
+        _ => return Err(unsupported()),
+    })
+}
+
+// Lowercase, and `_` is not allowed in Debian package names
+fn package_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+// `1.0.0-beta.1` must sort before `1.0.0`, which `~` does in Debian versions
+fn version(version: &str, revision: &str) -> String {
+    format!("{}-{revision}", version.replacen('-', "~", 1))
+}
+
+pub fn write(
+    mut file: File,
+    input: &PackageInput,
+    config: &DebConfig,
+) -> Result<(), Box<dyn Error>> {
+    let member = input.member;
+    let name = package_name(&member.name);
+    let mut tree = package::install_tree(
+        input,
+        &name,
+        &config.assets,
+        &config.systemd_units,
+        UNIT_DIR,
+    )?;
+    if let Some(copyright) = copyright(member) {
+        tree.push(TreeEntry::data(
+            format!("usr/share/doc/{name}/copyright"),
+            copyright,
+            0o644,
+        ));
+        package::complete_tree(&mut tree);
+    }
+
+    let mut installed_size = 0;
+    for entry in &tree {
+        installed_size += entry.size()?.div_ceil(1024);
+    }
+    let mut control_files = vec![TreeEntry::data(
+        "control",
+        control(input, config, &name, installed_size)?,
+        0o644,
+    )];
+    let conffiles: String = tree
+        .iter()
+        .filter(|e| e.path.starts_with("etc/") && !matches!(e.source, package::Source::Directory))
+        .map(|e| format!("/{}\n", e.path))
+        .collect();
+    if !conffiles.is_empty() {
+        control_files.push(TreeEntry::data("conffiles", conffiles, 0o644));
+    }
+    control_files.extend(maintainer_scripts(config));
+    control_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut control_tar = flate2::GzBuilder::new()
+        .mtime(0)
+        .write(Vec::new(), flate2::Compression::best());
+    control_tar.write_all(&package::tar_tree(&control_files, input.mtime)?)?;
+    let mut data_tar = xz2::write::XzEncoder::new(Vec::new(), 9);
+    data_tar.write_all(&package::tar_tree(&tree, input.mtime)?)?;
+
+    let mut deb = b"!<arch>\n".to_vec();
+    ar_member(&mut deb, "debian-binary", b"2.0\n", input.mtime);
+    ar_member(
+        &mut deb,
+        "control.tar.gz",
+        &control_tar.finish()?,
+        input.mtime,
+    );
+    ar_member(&mut deb, "data.tar.xz", &data_tar.finish()?, input.mtime);
+    file.write_all(&deb)?;
+    Ok(())
+}
+
+fn control(
+    input: &PackageInput,
+    config: &DebConfig,
+    name: &str,
+    installed_size: u64,
+) -> Result<String, Box<dyn Error>> {
+    let member = input.member;
+    let maintainer = config
+        .maintainer
+        .as_ref()
+        .or(member.authors.first())
+        .ok_or_else(|| {
+            format!(
+                "No maintainer for the {name} deb package: set `authors` in Cargo.toml or `maintainer` under [deb]"
+            )
+        })?;
+
+    let mut control = format!(
+        "Package: {name}\nVersion: {}\nArchitecture: {}\nMaintainer: {maintainer}\nInstalled-Size: {installed_size}\n",
+        version(&member.version, &config.revision),
+        architecture(input.target)?
+    );
+    if !config.depends.is_empty() {
+        control.push_str(&format!("Depends: {}\n", config.depends.join(", ")));
+    }
+    control.push_str(&format!(
+        "Section: {}\nPriority: {}\n",
+        config.section, config.priority
+    ));
+    if let Some(homepage) = member.homepage.as_ref().or(member.repository.as_ref()) {
+        control.push_str(&format!("Homepage: {homepage}\n"));
+    }
+
+    // Synopsis on the first line, the rest as the extended description
+    let description = member.description.as_deref().unwrap_or(&member.name).trim();
+    let mut lines = description.lines();
+    control.push_str(&format!(
+        "Description: {}\n",
+        lines.next().unwrap_or_default().trim()
+    ));
+    for line in lines {
+        if line.trim().is_empty() {
+            control.push_str(" .\n");
+        } else {
+            control.push_str(&format!(" {}\n", line.trim_end()));
+        }
+    }
+    Ok(control)
+}
+
+// Machine-readable debian/copyright carrying the Cargo.toml license
+fn copyright(member: &WorkspaceMember) -> Option<String> {
+    let license = member.license.as_ref()?;
+    let mut copyright = format!(
+        "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\nUpstream-Name: {}\n",
+        member.name
+    );
+    if let Some(repository) = &member.repository {
+        copyright.push_str(&format!("Source: {repository}\n"));
+    }
+    copyright.push_str("\nFiles: *\n");
+    if !member.authors.is_empty() {
+        copyright.push_str(&format!("Copyright: {}\n", member.authors.join(", ")));
+    }
+    copyright.push_str(&format!("License: {license}\n"));
+    Some(copyright)
+}
+
+fn maintainer_scripts(config: &DebConfig) -> Vec<TreeEntry> {
+    if config.systemd_units.is_empty() {
+        return Vec::new();
+    }
+    let units: Vec<String> = config
+        .systemd_units
+        .iter()
+        .filter_map(|unit| Some(unit.file_name()?.to_string_lossy().into_owned()))
+        .collect();
+    let units = units.join(" ");
+
+    let mut postinst = String::from(
+        "#!/bin/sh\nset -e\nif [ \"$1\" = \"configure\" ] && [ -d /run/systemd/system ]; then\n    systemctl daemon-reload\n",
+    );
+    if config.systemd_enable {
+        postinst.push_str(&format!("    systemctl enable {units}\n"));
+        postinst.push_str(&format!("    systemctl restart {units}\n"));
+    }
+    postinst.push_str("fi\n");
+    let prerm = format!(
+        "#!/bin/sh\nset -e\nif [ \"$1\" = \"remove\" ] && [ -d /run/systemd/system ]; then\n    systemctl stop {units} || true\n    systemctl disable {units} || true\nfi\n"
+    );
+    let postrm = "#!/bin/sh\nset -e\nif [ -d /run/systemd/system ]; then\n    systemctl daemon-reload || true\nfi\n";
+    vec![
+        TreeEntry::data("postinst", postinst, 0o755),
+        TreeEntry::data("prerm", prerm, 0o755),
+        TreeEntry::data("postrm", postrm, 0o755),
+    ]
+}
+
+// Member of a System V `ar` archive, the container of a .deb
+fn ar_member(out: &mut Vec<u8>, name: &str, data: &[u8], mtime: u64) {
+    out.extend_from_slice(
+        format!(
+            "{name:<16}{mtime:<12}{:<6}{:<6}{:<8o}{:<10}`\n",
+            0,
+            0,
+            0o100644,
+            data.len()
+        )
+        .as_bytes(),
+    );
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn triples_map_to_debian_architectures() {
+        assert_eq!(architecture("x86_64-unknown-linux-musl"), Ok("amd64"));
+        assert_eq!(architecture("aarch64-unknown-linux-gnu"), Ok("arm64"));
+        assert_eq!(architecture("armv7-unknown-linux-gnueabihf"), Ok("armhf"));
+        assert_eq!(architecture("arm-unknown-linux-gnueabi"), Ok("armel"));
+        assert!(architecture("x86_64-pc-windows-msvc").is_err());
+    }
+
+    #[test]
+    fn prerelease_versions_sort_before_the_release() {
+        assert_eq!(version("1.0.0-beta.1", "1"), "1.0.0~beta.1-1");
+        assert_eq!(package_name("My_Tool"), "my-tool");
+    }
+
+    // (name, data) of every member of an `ar` archive, checking the layout
+    fn ar_members(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert!(archive.starts_with(b"!<arch>\n"));
+        let mut members = Vec::new();
+        let mut offset = 8;
+        while offset < archive.len() {
+            assert_eq!(offset % 2, 0, "members start on even offsets");
+            let header = std::str::from_utf8(&archive[offset..offset + 60]).unwrap();
+            assert!(header.ends_with("`\n"));
+            let name = header[..16].trim_end().to_string();
+            let size: usize = header[48..58].trim_end().parse().unwrap();
+            assert_eq!(header[40..48].trim_end(), "100644");
+            let start = offset + 60;
+            members.push((name, archive[start..start + size].to_vec()));
+            offset = start + size;
+            if size % 2 == 1 {
+                assert_eq!(archive[offset], b'\n');
+                offset += 1;
+            }
+        }
+        members
+    }
+
+    fn tar_files(data: impl std::io::Read) -> Vec<(String, Vec<u8>)> {
+        let mut archive = tar::Archive::new(data);
+        let mut files = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+            files.push((path, content));
+        }
+        files
+    }
+
+    #[test]
+    fn odd_members_are_padded() {
+        let mut out = Vec::new();
+        ar_member(&mut out, "a", b"abc", 0);
+        ar_member(&mut out, "b", b"de", 0);
+        assert_eq!(out.len(), 60 + 4 + 60 + 2);
+        assert_eq!(&out[63..64], b"\n");
+        assert!(
+            out[64..].starts_with(b"b               0           0     0     100644  2         `\n")
+        );
+    }
+
+    #[test]
+    fn packages_hold_debian_binary_control_and_data() {
+        let dir = std::env::temp_dir().join(format!("rbt-deb-{}", std::process::id()));
+        fs::create_dir_all(dir.join("deploy")).unwrap();
+        fs::write(dir.join("app"), b"ELF").unwrap();
+        fs::write(dir.join("deploy/app.conf"), b"port = 80\n").unwrap();
+        fs::write(dir.join("deploy/app.service"), b"[Service]\n").unwrap();
+
+        let member = WorkspaceMember {
+            name: "my_app".to_string(),
+            version: "1.0.0-rc.1".to_string(),
+            description: Some("Does things\n\nAt length.".to_string()),
+            license: Some("MIT".to_string()),
+            authors: vec!["Jane Doe <jane@example.com>".to_string()],
+            homepage: None,
+            repository: Some("https://example.com/app".to_string()),
+            manifest_path: dir.join("Cargo.toml"),
+            bin_targets: vec!["app".to_string()],
+            example_targets: Vec::new(),
+        };
+        let input = PackageInput {
+            member: &member,
+            target: "x86_64-unknown-linux-gnu",
+            workspace_root: &dir,
+            files: vec![package::PackageFile {
+                source: dir.join("app"),
+                name: "app".to_string(),
+                kind: package::FileKind::Executable,
+            }],
+            mtime: 1_700_000_000,
+        };
+        let config = DebConfig {
+            depends: vec!["libc6 (>= 2.28)".to_string()],
+            assets: vec![Asset {
+                source: PathBuf::from("deploy/app.conf"),
+                dest: "/etc/app/".to_string(),
+                mode: None,
+            }],
+            systemd_units: vec![PathBuf::from("deploy/app.service")],
+            ..Default::default()
+        };
+        let path = dir.join("app.deb");
+        write(File::create(&path).unwrap(), &input, &config).unwrap();
+        assert_eq!(
+            file_name(&member, input.target, &config).unwrap(),
+            "my-app_1.0.0~rc.1-1_amd64.deb"
+        );
+
+        let members = ar_members(&fs::read(&path).unwrap());
+        let names: Vec<&str> = members.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["debian-binary", "control.tar.gz", "data.tar.xz"]);
+        assert_eq!(members[0].1, b"2.0\n");
+
+        let control = tar_files(flate2::read::GzDecoder::new(&members[1].1[..]));
+        let control_names: Vec<&str> = control.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            control_names,
+            ["conffiles", "control", "postinst", "postrm", "prerm"]
+        );
+        assert_eq!(control[0].1, b"/etc/app/app.conf\n");
+        let fields = String::from_utf8(control[1].1.clone()).unwrap();
+        assert!(
+            fields.starts_with(
+                "Package: my-app\nVersion: 1.0.0~rc.1-1\nArchitecture: amd64\nMaintainer: Jane Doe <jane@example.com>\nInstalled-Size: "
+            ),
+            "{fields}"
+        );
+        assert!(fields.ends_with(
+            "\nDepends: libc6 (>= 2.28)\nSection: utils\nPriority: optional\nHomepage: https://example.com/app\nDescription: Does things\n .\n At length.\n"
+        ));
+
+        let data = tar_files(xz2::read::XzDecoder::new(&members[2].1[..]));
+        let data_names: Vec<&str> = data.iter().map(|(name, _)| name.as_str()).collect();
+        for expected in [
+            "etc/app/app.conf",
+            "lib/systemd/system/app.service",
+            "usr/bin/app",
+            "usr/share/doc/my-app/copyright",
+        ] {
+            assert!(
+                data_names.contains(&expected),
+                "{expected} in {data_names:?}"
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
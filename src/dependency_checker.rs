@@ -1,8 +1,11 @@
 use std::error::Error;
 use std::fmt;
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 use crate::error::BuildToolError;
+use crate::features::FeatureSelection;
+use crate::workspace::{self, MemberCheck, WorkspaceMember};
 
 // 错误类型定义
 #[derive(Debug)]
@@ -29,7 +32,6 @@ impl fmt::Display for DepCheckError {
 impl Error for DepCheckError {}
 
 // 常量定义
-pub const CARGO_TOML: &str = "Cargo.toml";
 pub const UDEPS_CMD: &[&str] = &["cargo", "+nightly", "udeps", "--all-targets"];
 
 // 依赖位置信息
@@ -79,6 +81,58 @@ pub fn check_rust_nightly() -> Result<(), BuildToolError> {
     Ok(())
 }
 
+// `rustup` 命令：补装缺少的目标平台，以及 build-std 需要的 rust-src
+pub fn rustup_setup_commands(
+    toolchain: &str,
+    targets: &[String],
+    rust_src: bool,
+) -> Result<Vec<Command>, Box<dyn Error>> {
+    let toolchain = toolchain.trim_start_matches('+');
+    let mut commands = Vec::new();
+
+    let installed = rustup_list(&["target", "list", "--installed", "--toolchain", toolchain])?;
+    let missing: Vec<&String> = targets
+        .iter()
+        .filter(|target| !installed.contains(target))
+        .collect();
+    if !missing.is_empty() {
+        let mut cmd = Command::new("rustup");
+        cmd.args(["target", "add", "--toolchain", toolchain])
+            .args(missing);
+        commands.push(cmd);
+    }
+
+    if rust_src {
+        let components =
+            rustup_list(&["component", "list", "--installed", "--toolchain", toolchain])?;
+        if !components.iter().any(|c| c == "rust-src") {
+            let mut cmd = Command::new("rustup");
+            cmd.args(["component", "add", "--toolchain", toolchain, "rust-src"]);
+            commands.push(cmd);
+        }
+    }
+    Ok(commands)
+}
+
+fn rustup_list(args: &[&str]) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Command::new("rustup")
+        .args(args)
+        .output()
+        .map_err(|_| BuildToolError::RustupUnavailable)?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustup {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
 pub fn check_upx_lzma() -> Result<(), Box<dyn Error>> {
     let output = Command::new("upx").args(["--help"]).output().map_err(|e| {
         if e.kind() == std::io::ErrorKind::NotFound {
@@ -97,8 +151,8 @@ pub fn check_upx_lzma() -> Result<(), Box<dyn Error>> {
 }
 
 // 加载Cargo.toml内容
-pub fn load_cargo_toml() -> Result<String, DepCheckError> {
-    std::fs::read_to_string(CARGO_TOML).map_err(|_| DepCheckError::CargoTomlNotFound)
+pub fn load_cargo_toml(manifest_path: &Path) -> Result<String, DepCheckError> {
+    std::fs::read_to_string(manifest_path).map_err(|_| DepCheckError::CargoTomlNotFound)
 }
 
 // 解析Cargo.toml
@@ -109,9 +163,9 @@ pub fn parse_cargo_toml(content: &str) -> Result<toml::Value, DepCheckError> {
 }
 
 // 移除依赖项
-pub fn remove_dependency(dep: &str, location: &DependencyLocation) -> RemovalResult {
+pub fn remove_dependency(package: &str, dep: &str, location: &DependencyLocation) -> RemovalResult {
     let mut cmd = Command::new("cargo");
-    cmd.arg("remove").arg(dep);
+    cmd.args(["remove", "-p", package]).arg(dep);
 
     if let Some(flag) = &location.flag {
         cmd.arg(flag);
@@ -120,7 +174,7 @@ pub fn remove_dependency(dep: &str, location: &DependencyLocation) -> RemovalRes
     match cmd.output() {
         Ok(output) if output.status.success() => RemovalResult {
             success: true,
-            message: format!("Removed {} ({})", dep, location.section),
+            message: format!("Removed {} from {} ({})", dep, package, location.section),
         },
         Ok(output) => RemovalResult {
             success: false,
@@ -162,10 +216,18 @@ pub fn locate_dependency(
     Err(DepCheckError::DependencyNotFound(dep.to_string()))
 }
 
+// 构建cargo udeps命令
+pub fn udeps_command(package: &str, features: &FeatureSelection) -> Command {
+    let mut cmd = Command::new(UDEPS_CMD[0]);
+    cmd.args(&UDEPS_CMD[1..])
+        .args(["-p", package])
+        .args(features.cargo_args());
+    cmd
+}
+
 // 执行cargo udeps命令
-pub fn execute_udeps() -> Result<String, DepCheckError> {
-    let output = Command::new(UDEPS_CMD[0])
-        .args(&UDEPS_CMD[1..])
+pub fn execute_udeps(package: &str, features: &FeatureSelection) -> Result<String, DepCheckError> {
+    let output = udeps_command(package, features)
         .output()
         .map_err(|e| DepCheckError::CommandFailed(e.to_string()))?;
 
@@ -195,8 +257,8 @@ pub fn get_confirmation(prompt: &str) -> bool {
 }
 
 // 批量处理依赖项移除
-pub fn process_removals(deps: &[String]) -> Vec<RemovalResult> {
-    let cargo_content = match load_cargo_toml() {
+pub fn process_removals(member: &WorkspaceMember, deps: &[String]) -> Vec<RemovalResult> {
+    let cargo_content = match load_cargo_toml(&member.manifest_path) {
         Ok(content) => content,
         Err(e) => {
             return vec![RemovalResult {
@@ -219,7 +281,7 @@ pub fn process_removals(deps: &[String]) -> Vec<RemovalResult> {
     let mut results = Vec::new();
     for dep in deps {
         match locate_dependency(dep, &cargo_data) {
-            Ok(location) => results.push(remove_dependency(dep, &location)),
+            Ok(location) => results.push(remove_dependency(&member.name, dep, &location)),
             Err(e) => results.push(RemovalResult {
                 success: false,
                 message: format!("Failed to locate dependency: {e}"),
@@ -250,15 +312,42 @@ pub fn print_results(results: &[RemovalResult]) {
 }
 
 // 主流程
-pub fn check_unused_dependencies() -> Result<(), DepCheckError> {
+pub fn check_unused_dependencies(
+    members: &[&WorkspaceMember],
+    features: &FeatureSelection,
+) -> Result<(), DepCheckError> {
     if check_command("cargo-udeps").is_err() {
         return Err(DepCheckError::ToolMissing(
             "Please install cargo-udeps: cargo install cargo-udeps".into(),
         ));
     }
 
-    println!("Scanning for unused dependencies...");
-    let output = execute_udeps()?;
+    let mut checks = Vec::new();
+    for member in members {
+        let passed = match check_member(member, features) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+        };
+        checks.push(MemberCheck {
+            name: member.name.clone(),
+            passed,
+        });
+    }
+
+    workspace::summarize_checks("Depcheck", &checks)
+        .map_err(|e| DepCheckError::CommandFailed(e.to_string()))
+}
+
+// 检查单个工作区成员
+fn check_member(
+    member: &WorkspaceMember,
+    features: &FeatureSelection,
+) -> Result<(), DepCheckError> {
+    println!("Scanning {} for unused dependencies...", member.name);
+    let output = execute_udeps(&member.name, features)?;
     let unused_deps = parse_udeps_output(&output);
 
     if unused_deps.is_empty() {
@@ -270,7 +359,7 @@ pub fn check_unused_dependencies() -> Result<(), DepCheckError> {
     println!("{}", unused_deps.join("\n"));
 
     if get_confirmation("\nConfirm removal of these dependencies?") {
-        let results = process_removals(&unused_deps);
+        let results = process_removals(member, &unused_deps);
         print_results(&results);
     } else {
         println!("Operation cancelled");
@@ -0,0 +1,164 @@
+use std::ffi::OsStr;
+use std::process::Command;
+
+const DIFF_CONTEXT: usize = 3;
+
+// Prints a command the way `--dry-run` shows it: the command line first,
+// then every environment variable it would be given or have removed
+pub fn print_command(cmd: &Command) {
+    println!("$ {}", command_line(cmd));
+    for (key, value) in cmd.get_envs() {
+        let key = key.to_string_lossy();
+        match value {
+            Some(value) => println!("    {key}={}", env_value(value)),
+            None => println!("    (unset {key})"),
+        }
+    }
+}
+
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_+=.,/:@%".contains(c))
+    {
+        return arg.into_owned();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+// CARGO_ENCODED_RUSTFLAGS separates flags with 0x1f, which would be invisible
+fn env_value(value: &OsStr) -> String {
+    value.to_string_lossy().replace('\x1f', "\\x1f")
+}
+
+// Line-based unified diff with three lines of context
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old, &new);
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, DiffOp::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut i = 0;
+    while i < changed.len() {
+        // Merge changes whose context windows touch into one hunk
+        let start = changed[i].saturating_sub(DIFF_CONTEXT);
+        let mut end = changed[i];
+        while i < changed.len() && changed[i] <= end + 2 * DIFF_CONTEXT {
+            end = changed[i];
+            i += 1;
+        }
+        let end = (end + DIFF_CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let (old_before, new_before) = ops[..start].iter().fold((0, 0), |(o, n), op| match op {
+            DiffOp::Same(_) => (o + 1, n + 1),
+            DiffOp::Removed(_) => (o + 1, n),
+            DiffOp::Added(_) => (o, n + 1),
+        });
+        let old_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Removed(_)))
+            .count();
+        // An empty side is numbered by the line before it
+        let old_start = old_before + usize::from(old_len > 0);
+        let new_start = new_before + usize::from(new_len > 0);
+        out.push_str(&format!(
+            "@@ -{old_start},{old_len} +{new_start},{new_len} @@\n"
+        ));
+        for op in hunk {
+            match op {
+                DiffOp::Same(line) => out.push_str(&format!(" {line}\n")),
+                DiffOp::Removed(line) => out.push_str(&format!("-{line}\n")),
+                DiffOp::Added(line) => out.push_str(&format!("+{line}\n")),
+            }
+        }
+    }
+    out
+}
+
+enum DiffOp<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// Longest common subsequence; manifests are small enough for the quadratic table
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            ops.push(DiffOp::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(DiffOp::Removed(old[i]));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Added(new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| DiffOp::Removed(line)));
+    ops.extend(new[j..].iter().map(|line| DiffOp::Added(line)));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_shows_added_profile_with_context() {
+        let old = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n";
+        let new = format!("{old}\n[profile.rbt]\ninherits = 'release'\n");
+        assert_eq!(
+            unified_diff("Cargo.toml", old, &new),
+            "--- a/Cargo.toml\n+++ b/Cargo.toml\n@@ -4,3 +4,6 @@\n edition = \"2021\"\n \n [dependencies]\n+\n+[profile.rbt]\n+inherits = 'release'\n"
+        );
+    }
+
+    #[test]
+    fn commands_are_quoted_for_the_shell() {
+        let mut cmd = Command::new("cargo");
+        cmd.args(["build", "--features", "a b"])
+            .env("CARGO_ENCODED_RUSTFLAGS", "-Cpanic=abort\x1f-Copt-level=z");
+        assert_eq!(command_line(&cmd), "cargo build --features 'a b'");
+        assert_eq!(
+            env_value(OsStr::new("-Cpanic=abort\x1f-Copt-level=z")),
+            "-Cpanic=abort\\x1f-Copt-level=z"
+        );
+    }
+}
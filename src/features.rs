@@ -0,0 +1,66 @@
+// Cargo feature flags shared by the build, clippy and udeps invocations
+#[derive(Debug, Default, Clone)]
+pub struct FeatureSelection {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+}
+
+impl FeatureSelection {
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_selection_adds_no_arguments() {
+        assert!(FeatureSelection::default().cargo_args().is_empty());
+    }
+
+    #[test]
+    fn features_are_joined_into_one_argument() {
+        let selection = FeatureSelection {
+            features: vec!["minimal".to_string(), "serde/std".to_string()],
+            no_default_features: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.cargo_args(),
+            ["--features", "minimal,serde/std", "--no-default-features"]
+        );
+    }
+
+    #[test]
+    fn all_features_and_no_default_features_are_independent() {
+        let all = FeatureSelection {
+            all_features: true,
+            ..Default::default()
+        };
+        assert_eq!(all.cargo_args(), ["--all-features"]);
+
+        let both = FeatureSelection {
+            all_features: true,
+            no_default_features: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            both.cargo_args(),
+            ["--all-features", "--no-default-features"]
+        );
+    }
+}
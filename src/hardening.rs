@@ -0,0 +1,345 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use object::Endianness;
+use object::elf;
+use object::read::elf::{Dyn, FileHeader, ProgramHeader, Sym};
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::{Artifact, ArtifactKind};
+
+const CANARY_SYMBOLS: [&[u8]; 2] = [b"__stack_chk_fail", b"__stack_chk_guard"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relro {
+    None,
+    Partial, // PT_GNU_RELRO, but the GOT stays writable for lazy binding
+    Full,    // PT_GNU_RELRO plus BIND_NOW
+}
+
+impl Relro {
+    fn label(self) -> &'static str {
+        match self {
+            Relro::None => "no RELRO",
+            Relro::Partial => "partial RELRO",
+            Relro::Full => "full RELRO",
+        }
+    }
+}
+
+// Security properties of an ELF file
+#[derive(Debug, Clone, Serialize)]
+pub struct Hardening {
+    pub pie: bool,
+    pub relro: Relro,
+    pub nx: bool,
+    // From the symbol tables, so only visible when the binary is dynamically
+    // linked against libc or not stripped
+    pub canary: bool,
+    pub stripped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    pub shared_libraries: Vec<String>,
+    // Highest `GLIBC_*` symbol version the file requires
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_glibc: Option<String>,
+}
+
+impl Hardening {
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            if self.pie { "PIE" } else { "no PIE" }.to_string(),
+            self.relro.label().to_string(),
+            if self.nx { "NX" } else { "executable stack" }.to_string(),
+            if self.canary { "canary" } else { "no canary" }.to_string(),
+            if self.stripped { "stripped" } else { "symbols" }.to_string(),
+        ];
+        if let Some(glibc) = &self.min_glibc {
+            parts.push(format!("glibc >= {glibc}"));
+        }
+        if self.shared_libraries.is_empty() {
+            parts.push("no shared libraries".to_string());
+        } else {
+            parts.push(format!("needs {}", self.shared_libraries.join(", ")));
+        }
+        parts.join(", ")
+    }
+}
+
+// `None` for files that are not ELF
+pub fn inspect(path: &Path) -> Result<Option<Hardening>, Box<dyn Error>> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let hardening = match object::FileKind::parse(&*data) {
+        Ok(object::FileKind::Elf32) => inspect_elf::<elf::FileHeader32<Endianness>>(&data),
+        Ok(object::FileKind::Elf64) => inspect_elf::<elf::FileHeader64<Endianness>>(&data),
+        _ => return Ok(None),
+    };
+    hardening
+        .map(Some)
+        .map_err(|e| format!("Invalid ELF file {}: {e}", path.display()).into())
+}
+
+fn inspect_elf<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<Hardening, Box<dyn Error>> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+
+    let mut interpreter = None;
+    let mut has_relro = false;
+    // Without PT_GNU_STACK the kernel maps the stack executable
+    let mut nx = false;
+    for segment in header.program_headers(endian, data)? {
+        match segment.p_type(endian) {
+            elf::PT_INTERP => {
+                let path = segment
+                    .data(endian, data)
+                    .map_err(|()| "invalid PT_INTERP segment")?;
+                interpreter = Some(
+                    String::from_utf8_lossy(path)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                );
+            }
+            elf::PT_GNU_RELRO => has_relro = true,
+            elf::PT_GNU_STACK => nx = segment.p_flags(endian) & elf::PF_X == 0,
+            _ => {}
+        }
+    }
+
+    let sections = header.sections(endian, data)?;
+    let mut shared_libraries = Vec::new();
+    let mut bind_now = false;
+    let mut pie_flag = false;
+    if let Some((entries, link)) = sections.dynamic(endian, data)? {
+        let strings = sections.strings(endian, data, link)?;
+        for entry in entries {
+            let value = entry.val32(endian).unwrap_or_default();
+            match entry.tag32(endian) {
+                Some(elf::DT_NEEDED) => shared_libraries
+                    .push(String::from_utf8_lossy(entry.string(endian, strings)?).into_owned()),
+                Some(elf::DT_BIND_NOW) => bind_now = true,
+                Some(elf::DT_FLAGS) => bind_now |= value & elf::DF_BIND_NOW != 0,
+                Some(elf::DT_FLAGS_1) => {
+                    bind_now |= value & elf::DF_1_NOW != 0;
+                    pie_flag = value & elf::DF_1_PIE != 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let symtab = sections.symbols(endian, data, elf::SHT_SYMTAB)?;
+    let dynsym = sections.symbols(endian, data, elf::SHT_DYNSYM)?;
+    let mut canary = false;
+    for table in [&symtab, &dynsym] {
+        for symbol in table.iter() {
+            if let Ok(name) = symbol.name(endian, table.strings()) {
+                canary |= CANARY_SYMBOLS.contains(&name);
+            }
+        }
+    }
+
+    let mut min_glibc: Option<Vec<u32>> = None;
+    if let Some((mut needs, link)) = sections.gnu_verneed(endian, data)? {
+        let strings = sections.strings(endian, data, link)?;
+        while let Some((_, mut auxs)) = needs.next()? {
+            while let Some(aux) = auxs.next()? {
+                let name = String::from_utf8_lossy(aux.name(endian, strings)?).into_owned();
+                if let Some(version) = name.strip_prefix("GLIBC_").and_then(parse_version) {
+                    min_glibc = min_glibc.max(Some(version));
+                }
+            }
+        }
+    }
+
+    Ok(Hardening {
+        // Static PIE has neither an interpreter nor DF_1_PIE, but is ET_DYN
+        // with an entry point like any other PIE
+        pie: header.e_type(endian) == elf::ET_DYN
+            && (interpreter.is_some() || pie_flag || header.e_entry(endian).into() != 0),
+        relro: match (has_relro, bind_now) {
+            (false, _) => Relro::None,
+            (true, false) => Relro::Partial,
+            (true, true) => Relro::Full,
+        },
+        nx,
+        canary,
+        stripped: symtab.is_empty(),
+        interpreter,
+        shared_libraries,
+        min_glibc: min_glibc.map(|version| format_version(&version)),
+    })
+}
+
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn format_version(version: &[u32]) -> String {
+    version
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// `[hardening]` section of the project configuration; requirements apply to
+// ELF artifacts only
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HardeningConfig {
+    // Executables only, shared libraries are always position independent
+    pub require_pie: bool,
+    pub require_relro: Option<Relro>,
+    pub require_nx: bool,
+    pub require_canary: bool,
+    pub require_stripped: bool,
+    // e.g. "2.28", the oldest glibc the artifacts must run on
+    pub max_glibc: Option<String>,
+}
+
+impl HardeningConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(max) = &self.max_glibc {
+            parse_version(max)
+                .ok_or_else(|| format!("Invalid max-glibc `{max}` (expected e.g. 2.28)"))?;
+        }
+        Ok(())
+    }
+
+    pub fn violations(&self, artifact: &Artifact) -> Vec<String> {
+        let Some(hardening) = &artifact.hardening else {
+            return Vec::new();
+        };
+        // UPX leaves only its loader's headers to read, so what the
+        // decompressed code needs comes from the file before compression
+        let code = artifact.hardening_before_upx.as_ref().unwrap_or(hardening);
+        let name = artifact.file_name();
+        let mut violations = Vec::new();
+        if self.require_pie && artifact.kind == ArtifactKind::Executable && !hardening.pie {
+            violations.push(format!("{name} is not position independent"));
+        }
+        if let Some(required) = self.require_relro
+            && hardening.relro < required
+        {
+            violations.push(format!(
+                "{name} has {}, {} required",
+                hardening.relro.label(),
+                required.label()
+            ));
+        }
+        if self.require_nx && !hardening.nx {
+            violations.push(format!("{name} has an executable stack"));
+        }
+        if self.require_canary && !code.canary {
+            violations.push(format!("{name} has no stack protector"));
+        }
+        if self.require_stripped && !hardening.stripped {
+            violations.push(format!("{name} is not stripped"));
+        }
+        if let (Some(max), Some(min_glibc)) = (&self.max_glibc, &code.min_glibc)
+            && parse_version(min_glibc) > parse_version(max)
+        {
+            violations.push(format!(
+                "{name} requires glibc {min_glibc}, newer than {max}"
+            ));
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn glibc_versions_compare_numerically() {
+        assert!(parse_version("2.28") > parse_version("2.2.5"));
+        assert_eq!(parse_version("PRIVATE"), None);
+        assert_eq!(format_version(&[2, 17]), "2.17");
+
+        let config = HardeningConfig {
+            require_pie: true,
+            require_relro: Some(Relro::Full),
+            max_glibc: Some("2.28".to_string()),
+            ..Default::default()
+        };
+        let artifact = Artifact {
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            package: "app".to_string(),
+            name: "app".to_string(),
+            kind: ArtifactKind::Executable,
+            path: PathBuf::from("/t/app"),
+            size_before_upx: None,
+            hardening: Some(Hardening {
+                pie: true,
+                relro: Relro::Partial,
+                nx: true,
+                canary: false,
+                stripped: true,
+                interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_string()),
+                shared_libraries: vec!["libc.so.6".to_string()],
+                min_glibc: Some("2.34".to_string()),
+            }),
+            hardening_before_upx: None,
+        };
+        assert_eq!(
+            config.violations(&artifact),
+            vec![
+                "app has partial RELRO, full RELRO required",
+                "app requires glibc 2.34, newer than 2.28"
+            ]
+        );
+    }
+
+    #[test]
+    fn compressed_files_are_gated_on_what_ships() {
+        let config = HardeningConfig {
+            require_relro: Some(Relro::Full),
+            require_canary: true,
+            require_stripped: true,
+            max_glibc: Some("2.28".to_string()),
+            ..Default::default()
+        };
+        let before = Hardening {
+            pie: true,
+            relro: Relro::Full,
+            nx: true,
+            canary: true,
+            stripped: false,
+            interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_string()),
+            shared_libraries: vec!["libc.so.6".to_string()],
+            min_glibc: Some("2.34".to_string()),
+        };
+        let compressed = Hardening {
+            relro: Relro::None,
+            canary: false,
+            stripped: true,
+            interpreter: None,
+            shared_libraries: Vec::new(),
+            min_glibc: None,
+            ..before.clone()
+        };
+        let artifact = Artifact {
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            package: "app".to_string(),
+            name: "app".to_string(),
+            kind: ArtifactKind::Executable,
+            path: PathBuf::from("/t/app"),
+            size_before_upx: Some(4096),
+            hardening: Some(compressed),
+            hardening_before_upx: Some(before),
+        };
+        assert_eq!(
+            config.violations(&artifact),
+            vec![
+                "app has no RELRO, full RELRO required",
+                "app requires glibc 2.34, newer than 2.28"
+            ]
+        );
+    }
+}
@@ -2,11 +2,28 @@ use clap::{Arg, Command};
 use std::env;
 use std::error::Error;
 
+mod analyze;
+mod artifact;
+mod build_std;
 mod build_system;
 mod cargo_config;
+mod checksum;
+mod deb;
 mod dependency_checker;
+mod dry_run;
 mod error;
+mod features;
+mod hardening;
+mod oci;
+mod package;
 mod platform_helper;
+mod project_config;
+mod report;
+mod rpm;
+mod rustflags;
+mod size_history;
+mod static_linking;
+mod workspace;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = Command::new("rust_build_tool")
@@ -16,77 +33,639 @@ fn main() -> Result<(), Box<dyn Error>> {
         .subcommand(
             Command::new("build")
                 .about("Build the project")
+                .args(build_args()),
+        )
+        .subcommand(
+            Command::new("package")
+                .about("Build the project and archive the artifacts into dist/")
+                .args(build_args())
                 .arg(
-                    Arg::new("target")
-                        .long("target")
-                        .help("Target platform (default: auto-detect)"),
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .value_parser(["tar.gz", "tar.xz", "zip", "deb", "rpm", "oci"])
+                        .value_delimiter(',')
+                        .help("Package format, repeatable or comma-separated (default: tar.gz)")
+                        .action(clap::ArgAction::Append),
                 )
                 .arg(
-                    Arg::new("upx")
-                        .long("upx")
-                        .help("Enable UPX compression")
-                        .action(clap::ArgAction::SetTrue),
-                )
+                    Arg::new("dist-dir")
+                        .long("dist-dir")
+                        .value_name("DIR")
+                        .value_parser(clap::value_parser!(std::path::PathBuf))
+                        .help("Directory the archives are written to (default: dist)"),
+                ),
+        )
+        .subcommand(
+            Command::new("depcheck")
+                .about("Check and remove unused dependencies")
                 .arg(
-                    Arg::new("clean")
-                        .long("clean")
-                        .help("Clean before building")
-                        .action(clap::ArgAction::SetTrue),
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .help("Workspace member to check (repeatable, default: all members)")
+                        .action(clap::ArgAction::Append),
                 )
+                .args(feature_args()),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Break down the size of an executable by section, crate and function")
                 .arg(
-                    Arg::new("clippy")
-                        .long("clippy")
-                        .help("Run clippy lint checks after build")
-                        .action(clap::ArgAction::SetTrue),
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_parser(clap::value_parser!(std::path::PathBuf))
+                        .action(clap::ArgAction::Append),
                 )
                 .arg(
-                    Arg::new("deny")
-                        .long("deny")
-                        .help("Run cargo-deny checks after build")
-                        .action(clap::ArgAction::SetTrue),
+                    Arg::new("top")
+                        .long("top")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20")
+                        .help("Number of entries shown per table"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check a directory of artifacts against its SHA256SUMS/SHA512SUMS")
+                .arg(
+                    Arg::new("dir")
+                        .value_name("DIR")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(std::path::PathBuf)),
                 )
                 .arg(
-                    Arg::new("full-check")
-                        .long("full-check")
-                        .help("Run full workflow: clippy -> depcheck -> deny -> build (stops immediately on any failure)")
-                        .action(clap::ArgAction::SetTrue),
+                    Arg::new("manifest")
+                        .long("manifest")
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(std::path::PathBuf))
+                        .help(
+                            "Checksum manifest to use (default: SHA256SUMS and SHA512SUMS in DIR)",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the project configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about(
+                            "Print the effective configuration merged from Cargo.toml and rbt.toml",
+                        )
+                        .arg(
+                            Arg::new("package")
+                                .short('p')
+                                .long("package")
+                                .help("Include the package.metadata of this workspace member (repeatable)")
+                                .action(clap::ArgAction::Append),
+                        ),
                 ),
         )
-        .subcommand(Command::new("depcheck").about("Check and remove unused dependencies"))
         .get_matches();
 
     match matches.subcommand() {
-        Some(("build", sub_matches)) => {
-            let target = match sub_matches.get_one::<String>("target") {
-                Some(t) => t.to_string(),
-                None => platform_helper::get_default_target()?,
+        Some((command @ ("build" | "package"), sub_matches)) => {
+            let report_to_stdout = sub_matches.contains_id("report")
+                && !sub_matches.contains_id("report-file")
+                && !sub_matches.get_flag("dry-run");
+            let progress = if report_to_stdout {
+                Some(report::StdoutToStderr::redirect()?)
+            } else {
+                None
+            };
+            let workspace = workspace::Workspace::load()?;
+            let config = load_config(&workspace, &packages_arg(sub_matches))?;
+
+            // CLI flags take precedence over the project configuration
+            let mut targets = values_arg(sub_matches, "target");
+            if targets.is_empty() {
+                targets = config.targets.clone();
+            }
+            if targets.is_empty() {
+                targets.push(platform_helper::get_default_target()?);
+            }
+            let static_linking = match sub_matches.get_one::<String>("static") {
+                Some(method) => Some(method.parse::<static_linking::StaticLinking>()?),
+                None => config.static_linking,
             };
-            let use_upx = sub_matches.get_flag("upx");
-            let clean = sub_matches.get_flag("clean");
-
-            let build_system = build_system::BuildSystem::new(&target, use_upx, clean)?;
-            if sub_matches.get_flag("full-check") {
-                // Complete workflow: clippy -> depcheck -> deny -> build
-                build_system.run_clippy()?;
-                dependency_checker::check_unused_dependencies()?;
-                build_system.run_cargo_deny()?;
-                build_system.run()?;
+            let profile_mode = if switch(sub_matches, "edit-manifest", config.edit_manifest) {
+                cargo_config::ProfileMode::Manifest
             } else {
-                if sub_matches.get_flag("clippy") {
-                    build_system.run_clippy()?;
-                }
+                cargo_config::ProfileMode::Environment
+            };
 
-                if sub_matches.get_flag("deny") {
-                    build_system.run_cargo_deny()?;
+            let mode = match sub_matches.get_one::<String>("mode") {
+                Some(mode) => mode.parse()?,
+                None => config.mode,
+            };
+            let panic = match sub_matches.get_one::<String>("panic") {
+                Some(panic) => panic.parse()?,
+                None => config
+                    .panic
+                    .unwrap_or_else(|| build_std::PanicStrategy::default_for(mode)),
+            };
+            if let Some(method) = static_linking {
+                let mut static_targets = Vec::new();
+                for target in &targets {
+                    let static_target =
+                        static_linking::static_target(target, method, mode.toolchain())?;
+                    if static_target != *target {
+                        println!("Static build: {target} -> {static_target}");
+                    }
+                    if !static_targets.contains(&static_target) {
+                        static_targets.push(static_target);
+                    }
                 }
-                build_system.run()?;
+                targets = static_targets;
+            }
+            let preset = sub_matches
+                .get_one::<String>("preset")
+                .map(String::as_str)
+                .unwrap_or(config.preset_name());
+            let profile = sub_matches
+                .get_one::<String>("profile")
+                .cloned()
+                .unwrap_or_else(|| config.cargo_profile_name().to_string());
+            let build_std_crates = sub_matches
+                .get_many::<String>("build-std")
+                .map(|values| values.cloned().collect())
+                .or_else(|| config.build_std.clone());
+            let mut build_std_features = values_arg(sub_matches, "build-std-features");
+            if build_std_features.is_empty() {
+                build_std_features = config.build_std_features.clone();
             }
+
+            let mut rustflags = config.rustflags.clone();
+            rustflags.extend(
+                values_arg(sub_matches, "rustflags")
+                    .iter()
+                    .flat_map(|flags| flags.split_whitespace().map(str::to_string)),
+            );
+
+            let size_limits = size_history::SizeLimits {
+                max_size: sub_matches
+                    .get_one::<String>("max-size")
+                    .or(config.size.max_size.as_ref())
+                    .map(|size| size_history::parse_size(size))
+                    .transpose()?,
+                max_growth: sub_matches
+                    .get_one::<String>("max-growth")
+                    .or(config.size.max_growth.as_ref())
+                    .map(|growth| size_history::parse_percent(growth))
+                    .transpose()?,
+            };
+            let history_file = match &config.size.history_file {
+                Some(path) => workspace.root.join(path),
+                None => workspace.target_directory.join(size_history::HISTORY_FILE),
+            };
+
+            let package_options = match command {
+                "package" => Some(package_options(sub_matches, &config, &workspace.root)?),
+                _ => None,
+            };
+            if let Some(options) = &package_options
+                && options.formats.contains(&package::PackageFormat::Oci)
+            {
+                oci::check_targets(&targets, static_linking)?;
+            }
+
+            let build_system = build_system::BuildSystem::new(
+                build_system::BuildOptions {
+                    mode,
+                    build_std: build_std::BuildStdOptions {
+                        crates: build_std_crates,
+                        features: build_std_features,
+                        panic,
+                    },
+                    targets,
+                    keep_going: switch(sub_matches, "keep-going", config.keep_going),
+                    use_upx: switch(sub_matches, "upx", config.upx.enabled),
+                    upx_args: config.upx.args.clone(),
+                    clean: sub_matches.get_flag("clean"),
+                    profile,
+                    profile_mode,
+                    profile_settings: config.profile_settings(preset, panic)?,
+                    rustflags,
+                    packages: packages_arg(sub_matches),
+                    bins: values_arg(sub_matches, "bin"),
+                    all_bins: sub_matches.get_flag("bins"),
+                    examples: values_arg(sub_matches, "example"),
+                    features: feature_selection(sub_matches, &config),
+                    dry_run: sub_matches.get_flag("dry-run"),
+                    analyze: sub_matches.get_flag("analyze"),
+                    report: sub_matches.contains_id("report")
+                        || sub_matches.contains_id("report-file"),
+                    report_file: sub_matches
+                        .get_one::<std::path::PathBuf>("report-file")
+                        .cloned(),
+                    history_file,
+                    baseline: sub_matches.get_one::<String>("baseline").cloned(),
+                    size_limits,
+                    checksums: config.checksum_algorithms(),
+                    static_linking,
+                    hardening: config.hardening.clone(),
+                },
+                workspace,
+            )?;
+            if sub_matches.get_flag("dry-run") {
+                println!("Dry run: nothing is executed and no file is modified");
+            }
+            let mut result = run_build(&build_system, sub_matches, &config);
+            if let (Some(options), Ok(())) = (&package_options, &result) {
+                result = build_system.run_package(options);
+            }
+            // The build error, if any, takes precedence over a report failure
+            drop(progress);
+            let report = build_system.write_report(result.is_ok());
+            result?;
+            report?;
         }
-        Some(("depcheck", _)) => {
-            dependency_checker::check_unused_dependencies()?;
+        Some(("depcheck", sub_matches)) => {
+            let workspace = workspace::Workspace::load()?;
+            let config = load_config(&workspace, &packages_arg(sub_matches))?;
+            let members = workspace.select(&packages_arg(sub_matches))?;
+            let features = feature_selection(sub_matches, &config);
+            dependency_checker::check_unused_dependencies(&members, &features)?;
         }
+        Some(("analyze", sub_matches)) => {
+            let top = *sub_matches
+                .get_one::<usize>("top")
+                .unwrap_or(&analyze::DEFAULT_TOP);
+            for path in sub_matches
+                .get_many::<std::path::PathBuf>("file")
+                .into_iter()
+                .flatten()
+            {
+                println!("{}:", path.display());
+                analyze::analyze(path)?.print(top);
+            }
+        }
+        Some(("verify", sub_matches)) => {
+            let dir = sub_matches
+                .get_one::<std::path::PathBuf>("dir")
+                .ok_or("Missing directory")?;
+            checksum::verify_dir(
+                dir,
+                sub_matches
+                    .get_one::<std::path::PathBuf>("manifest")
+                    .map(|p| p.as_path()),
+            )?;
+        }
+        Some(("config", sub_matches)) => match sub_matches.subcommand() {
+            Some(("show", show_matches)) => {
+                let workspace = workspace::Workspace::load()?;
+                let config = load_config(&workspace, &packages_arg(show_matches))?;
+                print!("{}", config.to_effective_toml()?);
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(),
     }
 
     Ok(())
 }
+
+fn run_build(
+    build_system: &build_system::BuildSystem,
+    matches: &clap::ArgMatches,
+    config: &project_config::ProjectConfig,
+) -> Result<(), Box<dyn Error>> {
+    if matches.get_flag("full-check") {
+        // Complete workflow: clippy -> depcheck -> deny -> build
+        build_system.run_clippy()?;
+        build_system.run_depcheck()?;
+        build_system.run_cargo_deny()?;
+        return build_system.run();
+    }
+
+    if switch(matches, "clippy", config.checks.clippy) {
+        build_system.run_clippy()?;
+    }
+
+    if switch(matches, "depcheck", config.checks.depcheck) {
+        build_system.run_depcheck()?;
+    }
+
+    if switch(matches, "deny", config.checks.deny) {
+        build_system.run_cargo_deny()?;
+    }
+    build_system.run()
+}
+
+// Shared by `build` and `package`
+fn build_args() -> Vec<Arg> {
+    let mut args = vec![
+        Arg::new("target")
+            .long("target")
+            .help("Target platform, repeatable to build a matrix (default: auto-detect)")
+            .action(clap::ArgAction::Append),
+        Arg::new("mode")
+            .long("mode")
+            .visible_alias("toolchain")
+            .value_name("MODE")
+            .value_parser(["nightly", "stable"])
+            .help("nightly: build-std with immediate-abort panics; stable: release profile optimisations only (default: nightly)"),
+        Arg::new("build-std")
+            .long("build-std")
+            .value_name("CRATES")
+            .value_delimiter(',')
+            .help("Crates rebuilt by -Z build-std (default: std plus the panic runtime)"),
+        Arg::new("build-std-features")
+            .long("build-std-features")
+            .value_name("FEATURES")
+            .value_delimiter(',')
+            .help("Features passed to -Z build-std-features, e.g. optimize_for_size"),
+        Arg::new("panic")
+            .long("panic")
+            .value_name("STRATEGY")
+            .value_parser(["unwind", "abort", "immediate-abort"])
+            .help("Panic strategy (default: immediate-abort in nightly mode, abort in stable mode)"),
+        Arg::new("rustflags")
+            .long("rustflags")
+            .value_name("FLAGS")
+            .allow_hyphen_values(true)
+            .help("Extra rustc flags appended after the user's and the tool's own (repeatable)")
+            .action(clap::ArgAction::Append),
+        Arg::new("preset")
+            .long("preset")
+            .value_name("NAME")
+            .help("Release profile preset: size, speed, balanced, debuggable-release or one defined in the project configuration (default: size)"),
+        Arg::new("profile")
+            .long("profile")
+            .value_name("NAME")
+            .help("Cargo profile the preset is applied to; custom profiles inherit from release (default: rbt)"),
+        Arg::new("static")
+            .long("static")
+            .value_name("METHOD")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("musl")
+            .value_parser(["musl", "crt-static"])
+            .help("Build fully static Linux executables and verify them: musl switches to the *-linux-musl target, crt-static links glibc statically (default: musl)"),
+        Arg::new("package")
+            .short('p')
+            .long("package")
+            .help("Workspace member to build (repeatable, default: all binary members)")
+            .action(clap::ArgAction::Append),
+        Arg::new("bin")
+            .long("bin")
+            .value_name("NAME")
+            .help("Build only the named binary (repeatable)")
+            .action(clap::ArgAction::Append),
+        Arg::new("bins")
+            .long("bins")
+            .help("Build all binary targets")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("example")
+            .long("example")
+            .value_name("NAME")
+            .help("Build the named example (repeatable)")
+            .action(clap::ArgAction::Append),
+    ];
+    args.extend(switch_args(
+        "keep-going",
+        "no-keep-going",
+        "Continue with the remaining targets when one of them fails",
+    ));
+    args.extend(feature_args());
+    args.extend(switch_args("upx", "no-upx", "Enable UPX compression"));
+    args.extend(switch_args(
+        "clippy",
+        "no-clippy",
+        "Run clippy lint checks before the build",
+    ));
+    args.extend(switch_args(
+        "depcheck",
+        "no-depcheck",
+        "Check for unused dependencies before the build",
+    ));
+    args.extend(switch_args(
+        "deny",
+        "no-deny",
+        "Run cargo-deny checks before the build",
+    ));
+    args.extend(switch_args(
+        "edit-manifest",
+        "no-edit-manifest",
+        "Write the build profile into Cargo.toml during the build instead of passing it via environment variables",
+    ));
+    args.extend([
+        Arg::new("clean")
+            .long("clean")
+            .help("Clean before building")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("analyze")
+            .long("analyze")
+            .help("Show section, crate and function sizes of each artifact before UPX compression")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("Print the commands, their environment and the Cargo.toml changes without executing anything")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("report")
+            .long("report")
+            .value_name("FORMAT")
+            .value_parser(["json"])
+            .help("Emit a machine-readable build report when the run ends; on stdout, the build log moves to stderr"),
+        Arg::new("report-file")
+            .long("report-file")
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(std::path::PathBuf))
+            .help("Write the report to PATH instead of stdout (implies --report json)"),
+        Arg::new("max-size")
+            .long("max-size")
+            .value_name("SIZE")
+            .help("Fail when an artifact is larger than SIZE, e.g. 800K or 2M"),
+        Arg::new("max-growth")
+            .long("max-growth")
+            .value_name("PERCENT")
+            .help("Fail when an artifact grew by more than PERCENT against the baseline, e.g. 5%"),
+        Arg::new("baseline")
+            .long("baseline")
+            .value_name("COMMIT")
+            .help("Compare sizes against the build of COMMIT in the size history (default: the previous commit built)"),
+        Arg::new("full-check")
+            .long("full-check")
+            .help("Run full workflow: clippy -> depcheck -> deny -> build (stops immediately on any failure)")
+            .action(clap::ArgAction::SetTrue),
+    ]);
+    args
+}
+
+fn package_options(
+    matches: &clap::ArgMatches,
+    config: &project_config::ProjectConfig,
+    workspace_root: &std::path::Path,
+) -> Result<package::PackageOptions, Box<dyn Error>> {
+    let formats = values_arg(matches, "format")
+        .iter()
+        .map(|format| format.parse())
+        .collect::<Result<Vec<_>, _>>()?;
+    let formats = package::unique_formats(if formats.is_empty() {
+        &config.package.formats
+    } else {
+        &formats
+    });
+    let dist_dir = match matches.get_one::<std::path::PathBuf>("dist-dir") {
+        Some(dir) => dir.clone(),
+        None => workspace_root.join(&config.package.dist_dir),
+    };
+    Ok(package::PackageOptions {
+        formats,
+        dist_dir,
+        include: config.package.include.clone(),
+        deb: config.deb.clone(),
+        rpm: config.rpm.clone(),
+        oci: config.oci.clone(),
+    })
+}
+
+// `--<name>` and `--no-<name>`, which turns off what the project configuration
+// enables; the last one given wins
+fn switch_args(name: &'static str, negated: &'static str, help: &'static str) -> [Arg; 2] {
+    [
+        Arg::new(name)
+            .long(name)
+            .help(help)
+            .overrides_with(negated)
+            .action(clap::ArgAction::SetTrue),
+        Arg::new(negated)
+            .long(negated)
+            .help(format!(
+                "Turn off --{name} even when the project configuration enables it"
+            ))
+            .overrides_with(name)
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+// A switch from `switch_args`, falling back to the configured value
+fn switch(matches: &clap::ArgMatches, name: &str, configured: bool) -> bool {
+    if matches.get_flag(name) {
+        true
+    } else if matches.get_flag(&format!("no-{name}")) {
+        false
+    } else {
+        configured
+    }
+}
+
+// Members named with `-p` add their own `package.metadata` layer
+fn load_config(
+    workspace: &workspace::Workspace,
+    packages: &[String],
+) -> Result<project_config::ProjectConfig, Box<dyn Error>> {
+    let manifests: Vec<&std::path::Path> = if packages.is_empty() {
+        Vec::new()
+    } else {
+        workspace
+            .select(packages)?
+            .into_iter()
+            .map(|member| member.manifest_path.as_path())
+            .collect()
+    };
+    project_config::ProjectConfig::load(&workspace.root, &manifests)
+}
+
+fn packages_arg(matches: &clap::ArgMatches) -> Vec<String> {
+    values_arg(matches, "package")
+}
+
+fn values_arg(matches: &clap::ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+fn feature_args() -> [Arg; 3] {
+    [
+        Arg::new("features")
+            .short('F')
+            .long("features")
+            .value_name("FEATURES")
+            .help("Space or comma separated list of features to activate (repeatable)")
+            .action(clap::ArgAction::Append),
+        Arg::new("all-features")
+            .long("all-features")
+            .help("Activate all available features")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("no-default-features")
+            .long("no-default-features")
+            .help("Do not activate the `default` feature")
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+// Command-line features replace the configured list; the switches combine
+fn feature_selection(
+    matches: &clap::ArgMatches,
+    config: &project_config::ProjectConfig,
+) -> features::FeatureSelection {
+    let mut selected: Vec<String> = values_arg(matches, "features")
+        .iter()
+        .flat_map(|list| list.split([' ', ',']))
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_string)
+        .collect();
+    if selected.is_empty() {
+        selected = config.features.clone();
+    }
+    features::FeatureSelection {
+        features: selected,
+        all_features: matches.get_flag("all-features") || config.all_features,
+        no_default_features: matches.get_flag("no-default-features") || config.no_default_features,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_matches(args: &[&str]) -> clap::ArgMatches {
+        Command::new("build")
+            .args(build_args())
+            .try_get_matches_from(std::iter::once("build").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    #[test]
+    fn switches_override_the_configuration_both_ways() {
+        assert!(!switch(&build_matches(&["--no-upx"]), "upx", true));
+        assert!(switch(&build_matches(&["--upx"]), "upx", false));
+        assert!(switch(&build_matches(&[]), "upx", true));
+        assert!(!switch(&build_matches(&[]), "deny", false));
+        assert!(!switch(
+            &build_matches(&["--no-keep-going"]),
+            "keep-going",
+            true
+        ));
+        assert!(!switch(
+            &build_matches(&["--no-edit-manifest"]),
+            "edit-manifest",
+            true
+        ));
+        assert!(!switch(
+            &build_matches(&["--no-depcheck"]),
+            "depcheck",
+            true
+        ));
+    }
+
+    #[test]
+    fn the_last_of_a_switch_pair_wins() {
+        assert!(!switch(
+            &build_matches(&["--clippy", "--no-clippy"]),
+            "clippy",
+            true
+        ));
+        assert!(switch(
+            &build_matches(&["--no-clippy", "--clippy"]),
+            "clippy",
+            false
+        ));
+    }
+}
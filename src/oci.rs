@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::package::{self, Asset, FileKind, PackageInput, TreeEntry};
+use crate::static_linking::{self, StaticLinking};
+
+const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+// `[oci]` section of the project configuration
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OciConfig {
+    // Defaults to `<name>:<version>`
+    pub tag: Option<String>,
+    // Defaults to the package's only executable, or the one named after it
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Vec<String>,
+    pub env: Vec<String>,
+    pub user: Option<String>,
+    pub workdir: Option<String>,
+    // e.g. `8080/tcp`
+    pub exposed_ports: Vec<String>,
+    // Added to the `org.opencontainers.image.*` labels from Cargo.toml
+    pub labels: BTreeMap<String, String>,
+    pub assets: Vec<Asset>,
+}
+
+// GOARCH and variant of a Linux target triple, as used by image platforms
+pub fn platform(target: &str) -> Result<(&'static str, Option<&'static str>), String> {
+    let unsupported = || format!("No container platform for target `{target}`");
+    if !target.contains("-linux") {
+        return Err(unsupported());
+    }
+    let arch = target.split('-').next().unwrap_or_default();
+    Ok(match arch {
+        "x86_64" => ("amd64", None),
+        "i586" | "i686" => ("386", None),
+        "aarch64" => ("arm64", Some("v8")),
+        "armv7" | "thumbv7neon" => ("arm", Some("v7")),
+        "arm" | "armv6" => ("arm", Some("v6")),
+        "armv5te" => ("arm", Some("v5")),
+        "powerpc64le" => ("ppc64le", None),
+        "s390x" => ("s390x", None),
+        "riscv64gc" => ("riscv64", None),
+        "mips64el" => ("mips64le", None),
+        "loongarch64" => ("loong64", None),
+        _ => return Err(unsupported()),
+    })
+}
+
+// A scratch image has no dynamic loader or libc, so only musl targets, whose
+// executables are static by default, can go without `--static`
+pub fn check_targets(
+    targets: &[String],
+    static_linking: Option<StaticLinking>,
+) -> Result<(), String> {
+    if static_linking.is_some() {
+        return Ok(());
+    }
+    match targets
+        .iter()
+        .find(|target| !target.contains("-linux-musl"))
+    {
+        Some(target) => Err(format!(
+            "The oci format builds FROM scratch images, which cannot run the dynamically linked executables of {target}: pass --static or build for a *-linux-musl target"
+        )),
+        None => Ok(()),
+    }
+}
+
+// Image references are lowercase and cannot contain `+`
+fn default_tag(name: &str, version: &str) -> String {
+    format!(
+        "{}:{}",
+        name.to_ascii_lowercase(),
+        version.replace('+', "_")
+    )
+}
+
+// Image layout tarball with a single layer, plus the `manifest.json` that
+// `docker load` before Docker 25 expects
+pub fn write(
+    mut file: File,
+    input: &PackageInput,
+    config: &OciConfig,
+) -> Result<(), Box<dyn Error>> {
+    let member = input.member;
+    let (architecture, variant) = platform(input.target)?;
+    let tag = config
+        .tag
+        .clone()
+        .unwrap_or_else(|| default_tag(&member.name, &member.version));
+
+    for executable in input
+        .files
+        .iter()
+        .filter(|f| f.kind == FileKind::Executable)
+    {
+        static_linking::check(&executable.source).map_err(|e| {
+            format!("{e}, so it cannot run in a FROM scratch image: pass --static or build for a *-linux-musl target")
+        })?;
+    }
+
+    let tree = package::install_tree(input, &member.name, &config.assets, &[], "")?;
+    let layer_tar = package::tar_tree(&tree, input.mtime)?;
+    let mut encoder = flate2::GzBuilder::new()
+        .mtime(0)
+        .write(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&layer_tar)?;
+    let layer = encoder.finish()?;
+
+    let entrypoint = match &config.entrypoint {
+        Some(entrypoint) => entrypoint.clone(),
+        None => vec![format!("/usr/bin/{}", default_entrypoint(input)?)],
+    };
+    let created = timestamp(input.mtime);
+    let mut labels = BTreeMap::from([
+        (
+            "org.opencontainers.image.title".to_string(),
+            member.name.clone(),
+        ),
+        (
+            "org.opencontainers.image.version".to_string(),
+            member.version.clone(),
+        ),
+        (
+            "org.opencontainers.image.created".to_string(),
+            created.clone(),
+        ),
+    ]);
+    for (key, value) in [
+        ("description", &member.description),
+        ("licenses", &member.license),
+        ("url", &member.homepage),
+        ("source", &member.repository),
+    ] {
+        if let Some(value) = value {
+            labels.insert(format!("org.opencontainers.image.{key}"), value.clone());
+        }
+    }
+    if !member.authors.is_empty() {
+        labels.insert(
+            "org.opencontainers.image.authors".to_string(),
+            member.authors.join(", "),
+        );
+    }
+    labels.extend(config.labels.clone());
+
+    let mut env = vec!["PATH=/usr/local/bin:/usr/bin:/bin".to_string()];
+    env.extend(config.env.iter().cloned());
+    let mut image_config = json!({
+        "Entrypoint": entrypoint,
+        "Env": env,
+        "Labels": labels,
+    });
+    if !config.cmd.is_empty() {
+        image_config["Cmd"] = json!(config.cmd);
+    }
+    if let Some(user) = &config.user {
+        image_config["User"] = json!(user);
+    }
+    if let Some(workdir) = &config.workdir {
+        image_config["WorkingDir"] = json!(workdir);
+    }
+    if !config.exposed_ports.is_empty() {
+        let ports: BTreeMap<&str, serde_json::Value> = config
+            .exposed_ports
+            .iter()
+            .map(|port| (port.as_str(), json!({})))
+            .collect();
+        image_config["ExposedPorts"] = json!(ports);
+    }
+
+    let mut image = json!({
+        "created": created,
+        "architecture": architecture,
+        "os": "linux",
+        "config": image_config,
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": [digest(&layer_tar)],
+        },
+        "history": [{
+            "created": created,
+            "created_by": format!("rust-build-tool {}", env!("CARGO_PKG_VERSION")),
+        }],
+    });
+    if let Some(variant) = variant {
+        image["variant"] = json!(variant);
+    }
+    let image = serde_json::to_vec(&image)?;
+
+    let manifest = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_TYPE,
+        "config": descriptor(CONFIG_TYPE, &image),
+        "layers": [descriptor(LAYER_TYPE, &layer)],
+    }))?;
+    let mut manifest_descriptor = descriptor(MANIFEST_TYPE, &manifest);
+    manifest_descriptor["annotations"] = json!({
+        "io.containerd.image.name": tag,
+        "org.opencontainers.image.ref.name": tag,
+    });
+    manifest_descriptor["platform"] = json!({ "architecture": architecture, "os": "linux" });
+    if let Some(variant) = variant {
+        manifest_descriptor["platform"]["variant"] = json!(variant);
+    }
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [manifest_descriptor],
+    });
+    let docker_manifest = json!([{
+        "Config": blob_path(&image),
+        "RepoTags": [tag],
+        "Layers": [blob_path(&layer)],
+    }]);
+
+    let mut entries = vec![
+        TreeEntry::data("oci-layout", r#"{"imageLayoutVersion":"1.0.0"}"#, 0o644),
+        TreeEntry::data("index.json", serde_json::to_vec(&index)?, 0o644),
+        TreeEntry::data(
+            "manifest.json",
+            serde_json::to_vec(&docker_manifest)?,
+            0o644,
+        ),
+    ];
+    for blob in [layer, image, manifest] {
+        entries.push(TreeEntry::data(blob_path(&blob), blob, 0o644));
+    }
+    package::complete_tree(&mut entries);
+    file.write_all(&package::tar_tree(&entries, input.mtime)?)?;
+    Ok(())
+}
+
+fn default_entrypoint(input: &PackageInput) -> Result<String, Box<dyn Error>> {
+    let executables: Vec<&str> = input
+        .files
+        .iter()
+        .filter(|f| f.kind == FileKind::Executable)
+        .map(|f| f.name.as_str())
+        .collect();
+    match executables[..] {
+        [only] => Ok(only.to_string()),
+        _ => executables
+            .iter()
+            .find(|name| **name == input.member.name)
+            .map(|name| name.to_string())
+            .ok_or_else(|| {
+                format!(
+                    "Cannot pick the entrypoint of the {} image among {}: set `entrypoint` under [oci]",
+                    input.member.name,
+                    if executables.is_empty() {
+                        "no executables".to_string()
+                    } else {
+                        executables.join(", ")
+                    }
+                )
+                .into()
+            }),
+    }
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+fn blob_path(data: &[u8]) -> String {
+    format!("blobs/sha256/{:x}", Sha256::digest(data))
+}
+
+fn descriptor(media_type: &str, data: &[u8]) -> serde_json::Value {
+    json!({
+        "mediaType": media_type,
+        "digest": digest(data),
+        "size": data.len(),
+    })
+}
+
+// RFC 3339, UTC
+fn timestamp(epoch: u64) -> String {
+    let (year, month, day, hour, minute, second) = package::civil_time(epoch);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triples_map_to_image_platforms() {
+        assert_eq!(platform("x86_64-unknown-linux-musl"), Ok(("amd64", None)));
+        assert_eq!(
+            platform("armv7-unknown-linux-musleabihf"),
+            Ok(("arm", Some("v7")))
+        );
+        assert!(platform("x86_64-pc-windows-gnu").is_err());
+        assert_eq!(
+            default_tag("My-App", "1.0.0+build.5"),
+            "my-app:1.0.0_build.5"
+        );
+        assert_eq!(timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn dynamic_targets_need_static_linking() {
+        let musl = ["x86_64-unknown-linux-musl".to_string()];
+        let gnu = ["aarch64-unknown-linux-gnu".to_string()];
+        assert!(check_targets(&musl, None).is_ok());
+        assert!(check_targets(&gnu, None).is_err());
+        assert!(check_targets(&gnu, Some(StaticLinking::CrtStatic)).is_ok());
+    }
+
+    // The test harness itself is dynamically linked on glibc
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn dynamic_executables_are_refused() {
+        let dir = std::env::temp_dir().join(format!("rbt-oci-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let member = crate::workspace::WorkspaceMember {
+            name: "app".to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            license: None,
+            authors: Vec::new(),
+            homepage: None,
+            repository: None,
+            manifest_path: dir.join("Cargo.toml"),
+            bin_targets: vec!["app".to_string()],
+            example_targets: Vec::new(),
+        };
+        let input = PackageInput {
+            member: &member,
+            target: "x86_64-unknown-linux-gnu",
+            workspace_root: &dir,
+            files: vec![package::PackageFile {
+                source: std::env::current_exe().unwrap(),
+                name: "app".to_string(),
+                kind: FileKind::Executable,
+            }],
+            mtime: 0,
+        };
+        let path = dir.join("app.oci.tar");
+        let error = write(File::create(&path).unwrap(), &input, &OciConfig::default())
+            .unwrap_err()
+            .to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("is not statically linked"), "{error}");
+        assert!(error.contains("--static"), "{error}");
+    }
+
+    // ELF header of a static executable: no PT_INTERP, no dynamic section
+    fn static_elf() -> Vec<u8> {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        elf.resize(16, 0);
+        elf.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+        elf.extend_from_slice(&0xb7u16.to_le_bytes()); // EM_AARCH64
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&0x40_0000u64.to_le_bytes()); // entry
+        elf.extend_from_slice(&[0; 16]); // no program or section headers
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        for value in [64u16, 56, 0, 64, 0, 0] {
+            elf.extend_from_slice(&value.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn images_follow_the_layout_spec() {
+        let dir = std::env::temp_dir().join(format!("rbt-oci-layout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app"), static_elf()).unwrap();
+        let member = crate::workspace::WorkspaceMember {
+            name: "app".to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            license: Some("MIT".to_string()),
+            authors: Vec::new(),
+            homepage: None,
+            repository: None,
+            manifest_path: dir.join("Cargo.toml"),
+            bin_targets: vec!["app".to_string()],
+            example_targets: Vec::new(),
+        };
+        let input = PackageInput {
+            member: &member,
+            target: "aarch64-unknown-linux-musl",
+            workspace_root: &dir,
+            files: vec![package::PackageFile {
+                source: dir.join("app"),
+                name: "app".to_string(),
+                kind: FileKind::Executable,
+            }],
+            mtime: 0,
+        };
+        let path = dir.join("app.oci.tar");
+        write(File::create(&path).unwrap(), &input, &OciConfig::default()).unwrap();
+        let image = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut files = BTreeMap::new();
+        for entry in tar::Archive::new(&image[..]).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+            files.insert(name, data);
+        }
+        let json =
+            |name: &str| -> serde_json::Value { serde_json::from_slice(&files[name]).unwrap() };
+        // The blob a descriptor points to, checked against its digest and size
+        let blob = |descriptor: &serde_json::Value| -> Vec<u8> {
+            let digest = descriptor["digest"].as_str().unwrap();
+            let hex = digest.strip_prefix("sha256:").unwrap();
+            let data = files[&format!("blobs/sha256/{hex}")].clone();
+            assert_eq!(format!("{:x}", Sha256::digest(&data)), hex);
+            assert_eq!(descriptor["size"], data.len());
+            data
+        };
+
+        assert_eq!(json("oci-layout"), json!({ "imageLayoutVersion": "1.0.0" }));
+        let index = json("index.json");
+        assert_eq!(index["manifests"].as_array().unwrap().len(), 1);
+        let descriptor = &index["manifests"][0];
+        assert_eq!(descriptor["mediaType"], MANIFEST_TYPE);
+        assert_eq!(
+            descriptor["annotations"]["org.opencontainers.image.ref.name"],
+            "app:1.0.0"
+        );
+        assert_eq!(
+            descriptor["platform"],
+            json!({ "architecture": "arm64", "os": "linux", "variant": "v8" })
+        );
+
+        let manifest: serde_json::Value = serde_json::from_slice(&blob(descriptor)).unwrap();
+        assert_eq!(manifest["config"]["mediaType"], CONFIG_TYPE);
+        let config: serde_json::Value = serde_json::from_slice(&blob(&manifest["config"])).unwrap();
+        assert_eq!(manifest["layers"][0]["mediaType"], LAYER_TYPE);
+        let layer = blob(&manifest["layers"][0]);
+
+        let mut layer_tar = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(&layer[..]),
+            &mut layer_tar,
+        )
+        .unwrap();
+        assert_eq!(config["rootfs"]["diff_ids"], json!([digest(&layer_tar)]));
+        let layer_files: Vec<String> = tar::Archive::new(&layer_tar[..])
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(layer_files, ["usr/", "usr/bin/", "usr/bin/app"]);
+
+        assert_eq!(config["architecture"], "arm64");
+        assert_eq!(config["variant"], "v8");
+        assert_eq!(config["os"], "linux");
+        assert_eq!(config["config"]["Entrypoint"], json!(["/usr/bin/app"]));
+        assert_eq!(
+            config["config"]["Labels"]["org.opencontainers.image.licenses"],
+            "MIT"
+        );
+        assert_eq!(json("manifest.json")[0]["RepoTags"], json!(["app:1.0.0"]));
+    }
+}
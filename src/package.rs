@@ -0,0 +1,674 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::{Artifact, ArtifactKind};
+use crate::deb::{self, DebConfig};
+use crate::oci::{self, OciConfig};
+use crate::rpm::{self, RpmConfig};
+use crate::workspace::WorkspaceMember;
+
+pub const DEFAULT_DIST_DIR: &str = "dist";
+// 1980-01-01, the earliest time a ZIP entry can carry
+const ZIP_EPOCH: u64 = 315_532_800;
+// Picked up by default when `include` is not configured
+const DEFAULT_EXTRA_PREFIXES: [&str; 3] = ["README", "LICENSE", "COPYING"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PackageFormat {
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "deb")]
+    Deb,
+    #[serde(rename = "rpm")]
+    Rpm,
+    #[serde(rename = "oci")]
+    Oci,
+}
+
+impl PackageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PackageFormat::TarGz => "tar.gz",
+            PackageFormat::TarXz => "tar.xz",
+            PackageFormat::Zip => "zip",
+            PackageFormat::Deb => "deb",
+            PackageFormat::Rpm => "rpm",
+            PackageFormat::Oci => "oci.tar",
+        }
+    }
+}
+
+impl std::str::FromStr for PackageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar.gz" => Ok(PackageFormat::TarGz),
+            "tar.xz" => Ok(PackageFormat::TarXz),
+            "zip" => Ok(PackageFormat::Zip),
+            "deb" => Ok(PackageFormat::Deb),
+            "rpm" => Ok(PackageFormat::Rpm),
+            "oci" => Ok(PackageFormat::Oci),
+            _ => Err(format!(
+                "Unknown package format `{s}` (expected tar.gz, tar.xz, zip, deb, rpm or oci)"
+            )),
+        }
+    }
+}
+
+// Drops repeated formats, keeping the first occurrence and the order
+pub fn unique_formats(formats: &[PackageFormat]) -> Vec<PackageFormat> {
+    let mut unique = Vec::new();
+    for format in formats {
+        if !unique.contains(format) {
+            unique.push(*format);
+        }
+    }
+    unique
+}
+
+// What `package` produces and where
+pub struct PackageOptions {
+    pub formats: Vec<PackageFormat>,
+    pub dist_dir: PathBuf,
+    pub include: Option<Vec<PathBuf>>,
+    pub deb: DebConfig,
+    pub rpm: RpmConfig,
+    pub oci: OciConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Executable,
+    Library,
+    Doc, // README, LICENSE and configured extras
+}
+
+impl FileKind {
+    pub fn mode(self) -> u32 {
+        match self {
+            FileKind::Executable => 0o755,
+            FileKind::Library | FileKind::Doc => 0o644,
+        }
+    }
+}
+
+// A file placed in the package, `name` being its path inside an archive
+pub struct PackageFile {
+    pub source: PathBuf,
+    pub name: String,
+    pub kind: FileKind,
+}
+
+// Everything one member contributes for one target
+pub struct PackageInput<'a> {
+    pub member: &'a WorkspaceMember,
+    pub target: &'a str,
+    pub workspace_root: &'a Path,
+    pub files: Vec<PackageFile>,
+    pub mtime: u64,
+}
+
+impl PackageInput<'_> {
+    pub fn base_name(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.member.name, self.member.version, self.target
+        )
+    }
+}
+
+// Name of the file `write_package` produces
+pub fn file_name(
+    format: PackageFormat,
+    member: &WorkspaceMember,
+    target: &str,
+    options: &PackageOptions,
+) -> Result<String, Box<dyn Error>> {
+    match format {
+        PackageFormat::Deb => deb::file_name(member, target, &options.deb),
+        PackageFormat::Rpm => rpm::file_name(member, target, &options.rpm),
+        _ => Ok(format!(
+            "{}-{}-{target}.{}",
+            member.name,
+            member.version,
+            format.extension()
+        )),
+    }
+}
+
+// Artifacts first, then the extra files, each sorted by name
+pub fn collect_files(
+    member: &WorkspaceMember,
+    workspace_root: &Path,
+    artifacts: &[&Artifact],
+    include: Option<&[PathBuf]>,
+) -> Result<Vec<PackageFile>, Box<dyn Error>> {
+    let mut files: Vec<PackageFile> = artifacts
+        .iter()
+        .map(|artifact| PackageFile {
+            source: artifact.path.clone(),
+            name: artifact.file_name(),
+            kind: match artifact.kind {
+                ArtifactKind::Executable => FileKind::Executable,
+                ArtifactKind::Library => FileKind::Library,
+            },
+        })
+        .collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let member_dir = member.manifest_path.parent().unwrap_or(workspace_root);
+    let mut extras = match include {
+        Some(include) => include
+            .iter()
+            .map(|path| resolve_include(path, member_dir, workspace_root))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => default_extras(member_dir, workspace_root)?,
+    };
+    extras.sort_by(|a, b| a.name.cmp(&b.name));
+    files.extend(extras);
+    Ok(files)
+}
+
+// Relative to the member's directory, falling back to the workspace root
+fn resolve_include(
+    path: &Path,
+    member_dir: &Path,
+    workspace_root: &Path,
+) -> Result<PackageFile, Box<dyn Error>> {
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!(
+            "Package include `{}` must be a relative path inside the project",
+            path.display()
+        )
+        .into());
+    }
+    let source = [member_dir, workspace_root]
+        .iter()
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| format!("Package include `{}` not found", path.display()))?;
+    Ok(PackageFile {
+        source,
+        name: path.to_string_lossy().replace('\\', "/"),
+        kind: FileKind::Doc,
+    })
+}
+
+fn default_extras(
+    member_dir: &Path,
+    workspace_root: &Path,
+) -> Result<Vec<PackageFile>, Box<dyn Error>> {
+    let mut extras = Vec::new();
+    for prefix in DEFAULT_EXTRA_PREFIXES {
+        for dir in [member_dir, workspace_root] {
+            let mut found = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.to_ascii_uppercase().starts_with(prefix) && entry.path().is_file() {
+                    found.push(PackageFile {
+                        source: entry.path(),
+                        name,
+                        kind: FileKind::Doc,
+                    });
+                }
+            }
+            if !found.is_empty() {
+                extras.extend(found);
+                break;
+            }
+        }
+    }
+    Ok(extras)
+}
+
+// SOURCE_DATE_EPOCH when set, so rebuilding the same sources gives identical packages
+pub fn source_date_epoch() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(ZIP_EPOCH)
+}
+
+pub fn write_package(
+    format: PackageFormat,
+    input: &PackageInput,
+    options: &PackageOptions,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let name = file_name(format, input.member, input.target, options)?;
+    fs::create_dir_all(&options.dist_dir)?;
+    let path = options.dist_dir.join(name);
+    let file =
+        File::create(&path).map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+
+    match format {
+        PackageFormat::TarGz => {
+            let encoder = flate2::GzBuilder::new()
+                .mtime(0)
+                .write(file, flate2::Compression::best());
+            write_tar(encoder, input)?.finish()?;
+        }
+        PackageFormat::TarXz => {
+            let encoder = xz2::write::XzEncoder::new(file, 9);
+            write_tar(encoder, input)?.finish()?;
+        }
+        PackageFormat::Zip => write_zip(file, input)?,
+        PackageFormat::Deb => deb::write(file, input, &options.deb)?,
+        PackageFormat::Rpm => rpm::write(file, input, &options.rpm)?,
+        PackageFormat::Oci => oci::write(file, input, &options.oci)?,
+    }
+    Ok(path)
+}
+
+// Entries live under a `name-version-target/` directory
+fn write_tar<W: Write>(writer: W, input: &PackageInput) -> Result<W, Box<dyn Error>> {
+    let base = input.base_name();
+    let mut builder = tar::Builder::new(writer);
+
+    let mut header = tar_header(input.mtime, 0o755);
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    builder.append_data(&mut header, format!("{base}/"), io::empty())?;
+
+    for file in &input.files {
+        let mut source = File::open(&file.source)
+            .map_err(|e| format!("Failed to open {}: {e}", file.source.display()))?;
+        let mut header = tar_header(input.mtime, file.kind.mode());
+        header.set_size(source.metadata()?.len());
+        builder.append_data(&mut header, format!("{base}/{}", file.name), &mut source)?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+// A file or directory of the tree a system package installs, `path` being
+// relative to `/`
+pub struct TreeEntry {
+    pub path: String,
+    pub source: Source,
+    pub mode: u32,
+}
+
+pub enum Source {
+    Directory,
+    File(PathBuf),
+    Data(Vec<u8>),
+}
+
+impl TreeEntry {
+    pub fn file(path: impl Into<String>, source: &Path, mode: u32) -> Self {
+        Self {
+            path: path.into(),
+            source: Source::File(source.to_path_buf()),
+            mode,
+        }
+    }
+
+    pub fn data(path: impl Into<String>, data: impl Into<Vec<u8>>, mode: u32) -> Self {
+        Self {
+            path: path.into(),
+            source: Source::Data(data.into()),
+            mode,
+        }
+    }
+
+    pub fn size(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(match &self.source {
+            Source::Directory => 0,
+            Source::File(path) => fs::metadata(path)
+                .map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+                .len(),
+            Source::Data(data) => data.len() as u64,
+        })
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match &self.source {
+            Source::Directory => Vec::new(),
+            Source::File(path) => {
+                fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+            }
+            Source::Data(data) => data.clone(),
+        })
+    }
+}
+
+// Extra file installed by a system package
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Asset {
+    // Relative to the workspace root
+    pub source: PathBuf,
+    // Absolute; a trailing `/` keeps the source's file name
+    pub dest: String,
+    pub mode: Option<u32>,
+}
+
+// FHS layout shared by system packages: executables in /usr/bin, libraries in
+// /usr/lib, docs in /usr/share/doc/<package>, then the systemd units and assets
+pub fn install_tree(
+    input: &PackageInput,
+    package_name: &str,
+    assets: &[Asset],
+    systemd_units: &[PathBuf],
+    unit_dir: &str,
+) -> Result<Vec<TreeEntry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for file in &input.files {
+        let path = match file.kind {
+            FileKind::Executable => format!("usr/bin/{}", file.name),
+            FileKind::Library => format!("usr/lib/{}", file.name),
+            FileKind::Doc => format!("usr/share/doc/{package_name}/{}", file.name),
+        };
+        entries.push(TreeEntry::file(path, &file.source, file.kind.mode()));
+    }
+    for unit in systemd_units {
+        let source = input.workspace_root.join(unit);
+        let name = unit
+            .file_name()
+            .ok_or_else(|| format!("Invalid systemd unit path {}", unit.display()))?;
+        entries.push(TreeEntry::file(
+            format!("{unit_dir}/{}", name.to_string_lossy()),
+            &source,
+            0o644,
+        ));
+    }
+    for asset in assets {
+        entries.push(asset_entry(asset, input.workspace_root)?);
+    }
+
+    for entry in &entries {
+        if let Source::File(source) = &entry.source
+            && !source.is_file()
+        {
+            return Err(format!("Package file {} not found", source.display()).into());
+        }
+    }
+    let mut seen = std::collections::BTreeSet::new();
+    if let Some(duplicate) = entries.iter().find(|e| !seen.insert(&e.path)) {
+        return Err(format!(
+            "More than one file would be installed as /{}",
+            duplicate.path
+        )
+        .into());
+    }
+    complete_tree(&mut entries);
+    Ok(entries)
+}
+
+fn asset_entry(asset: &Asset, workspace_root: &Path) -> Result<TreeEntry, Box<dyn Error>> {
+    let dest = asset
+        .dest
+        .strip_prefix('/')
+        .filter(|dest| !dest.split('/').any(|c| c == ".." || c == "."))
+        .ok_or_else(|| {
+            format!(
+                "Asset destination `{}` must be an absolute path",
+                asset.dest
+            )
+        })?;
+    let path = match dest.strip_suffix('/') {
+        Some(dir) => {
+            let name = asset
+                .source
+                .file_name()
+                .ok_or_else(|| format!("Invalid asset source {}", asset.source.display()))?
+                .to_string_lossy();
+            if dir.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{dir}/{name}")
+            }
+        }
+        None if !dest.is_empty() => dest.to_string(),
+        None => return Err("Asset destination `/` is not a file path".into()),
+    };
+    Ok(TreeEntry::file(
+        path,
+        &workspace_root.join(&asset.source),
+        asset.mode.unwrap_or(0o644),
+    ))
+}
+
+// Adds the missing parent directories and sorts the tree by path
+pub fn complete_tree(entries: &mut Vec<TreeEntry>) {
+    let mut dirs: Vec<String> = Vec::new();
+    for entry in entries.iter() {
+        let mut path = entry.path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            dirs.push(parent.to_string());
+            path = parent;
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs {
+        if !entries.iter().any(|e| e.path == dir) {
+            entries.push(TreeEntry {
+                path: dir,
+                source: Source::Directory,
+                mode: 0o755,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+}
+
+// Uncompressed tar of a tree
+pub fn tar_tree(entries: &[TreeEntry], mtime: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for entry in entries {
+        let mut header = tar_header(mtime, entry.mode);
+        match &entry.source {
+            Source::Directory => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                builder.append_data(&mut header, format!("{}/", entry.path), io::empty())?;
+            }
+            _ => {
+                let data = entry.read()?;
+                header.set_size(data.len() as u64);
+                builder.append_data(&mut header, &entry.path, &*data)?;
+            }
+        }
+    }
+    Ok(builder.into_inner()?)
+}
+
+pub fn tar_header(mtime: u64, mode: u32) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(mtime);
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+fn write_zip(file: File, input: &PackageInput) -> Result<(), Box<dyn Error>> {
+    let base = input.base_name();
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip_time(input.mtime)?);
+
+    zip.add_directory(format!("{base}/"), options.unix_permissions(0o755))?;
+    for file in &input.files {
+        let mut source = File::open(&file.source)
+            .map_err(|e| format!("Failed to open {}: {e}", file.source.display()))?;
+        zip.start_file(
+            format!("{base}/{}", file.name),
+            options.unix_permissions(file.kind.mode()),
+        )?;
+        io::copy(&mut source, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn zip_time(epoch: u64) -> Result<zip::DateTime, Box<dyn Error>> {
+    let (year, month, day, hour, minute, second) = civil_time(epoch.max(ZIP_EPOCH));
+    zip::DateTime::from_date_and_time(year as u16, month, day, hour, minute, second)
+        .map_err(|e| format!("Cannot store timestamp {epoch} in a ZIP archive: {e}").into())
+}
+
+// UTC calendar fields of a Unix timestamp (days-from-civil, inverted)
+pub fn civil_time(epoch: u64) -> (u64, u8, u8, u8, u8, u8) {
+    let days = epoch / 86_400;
+    let seconds = epoch % 86_400;
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (
+        year,
+        month as u8,
+        day as u8,
+        (seconds / 3_600) as u8,
+        (seconds % 3_600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_formats_are_written_once() {
+        assert_eq!(
+            unique_formats(&[
+                PackageFormat::TarGz,
+                PackageFormat::Zip,
+                PackageFormat::TarGz,
+                PackageFormat::Zip
+            ]),
+            [PackageFormat::TarGz, PackageFormat::Zip]
+        );
+    }
+
+    #[test]
+    fn civil_time_matches_known_dates() {
+        assert_eq!(civil_time(ZIP_EPOCH), (1980, 1, 1, 0, 0, 0));
+        assert_eq!(civil_time(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(civil_time(1_760_842_861), (2025, 10, 19, 3, 1, 1));
+    }
+
+    #[test]
+    fn includes_must_stay_inside_the_project() {
+        let root = Path::new("/ws");
+        assert!(resolve_include(Path::new("../secret"), root, root).is_err());
+        assert!(resolve_include(Path::new("/etc/passwd"), root, root).is_err());
+    }
+
+    fn member(dir: &Path) -> WorkspaceMember {
+        WorkspaceMember {
+            name: "app".to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            license: None,
+            authors: Vec::new(),
+            homepage: None,
+            repository: None,
+            manifest_path: dir.join("Cargo.toml"),
+            bin_targets: vec!["app".to_string()],
+            example_targets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn archives_are_reproducible() {
+        let dir = std::env::temp_dir().join(format!("rbt-package-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app"), b"ELF").unwrap();
+        fs::write(dir.join("README.md"), b"# app\n").unwrap();
+        let member = member(&dir);
+        let mtime = 1_700_000_000;
+        let input = PackageInput {
+            member: &member,
+            target: "x86_64-unknown-linux-gnu",
+            workspace_root: &dir,
+            files: vec![
+                PackageFile {
+                    source: dir.join("app"),
+                    name: "app".to_string(),
+                    kind: FileKind::Executable,
+                },
+                PackageFile {
+                    source: dir.join("README.md"),
+                    name: "README.md".to_string(),
+                    kind: FileKind::Doc,
+                },
+            ],
+            mtime,
+        };
+        let base = "app-1.0.0-x86_64-unknown-linux-gnu";
+        let expected = [
+            (format!("{base}/"), 0o755),
+            (format!("{base}/app"), 0o755),
+            (format!("{base}/README.md"), 0o644),
+        ];
+
+        for format in [
+            PackageFormat::TarGz,
+            PackageFormat::TarXz,
+            PackageFormat::Zip,
+        ] {
+            let write = |run: &str| {
+                let options = PackageOptions {
+                    formats: vec![format],
+                    dist_dir: dir.join(run),
+                    include: None,
+                    deb: DebConfig::default(),
+                    rpm: RpmConfig::default(),
+                    oci: OciConfig::default(),
+                };
+                fs::read(write_package(format, &input, &options).unwrap()).unwrap()
+            };
+            let archive = write("first");
+            assert_eq!(archive, write("second"), "{format:?} is reproducible");
+
+            let mut entries = Vec::new();
+            if format == PackageFormat::Zip {
+                let mut zip = zip::ZipArchive::new(io::Cursor::new(archive)).unwrap();
+                for index in 0..zip.len() {
+                    let file = zip.by_index(index).unwrap();
+                    assert!(file.last_modified() == Some(zip_time(mtime).unwrap()));
+                    entries.push((
+                        file.name().unwrap().into_owned(),
+                        file.unix_mode().unwrap() & 0o7777,
+                    ));
+                }
+            } else {
+                let reader: Box<dyn io::Read> = match format {
+                    PackageFormat::TarGz => Box::new(flate2::read::GzDecoder::new(&archive[..])),
+                    _ => Box::new(xz2::read::XzDecoder::new(&archive[..])),
+                };
+                for entry in tar::Archive::new(reader).entries().unwrap() {
+                    let header = entry.unwrap().header().clone();
+                    assert_eq!(header.mtime().unwrap(), mtime);
+                    assert_eq!((header.uid().unwrap(), header.gid().unwrap()), (0, 0));
+                    entries.push((
+                        header.path().unwrap().to_string_lossy().into_owned(),
+                        header.mode().unwrap(),
+                    ));
+                }
+            }
+            assert_eq!(entries, expected, "{format:?} entries");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
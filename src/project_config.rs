@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::build_std::PanicStrategy;
+use crate::build_system::BuildMode;
+use crate::cargo_config::{self, ProfileSettings};
+use crate::checksum::Algorithm;
+use crate::deb::DebConfig;
+use crate::hardening::HardeningConfig;
+use crate::oci::OciConfig;
+use crate::package::{self, PackageFormat};
+use crate::rpm::RpmConfig;
+use crate::static_linking::StaticLinking;
+
+pub const CONFIG_FILE: &str = "rbt.toml";
+pub const METADATA_KEY: &str = "rust-build-tool";
+
+// Per-project settings read from `[package.metadata.rust-build-tool]`,
+// `[workspace.metadata.rust-build-tool]` and `rbt.toml`
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub mode: BuildMode,
+    pub panic: Option<PanicStrategy>,
+    pub build_std: Option<Vec<String>>,
+    pub build_std_features: Vec<String>,
+    pub targets: Vec<String>,
+    #[serde(rename = "static")]
+    pub static_linking: Option<StaticLinking>,
+    pub keep_going: bool,
+    pub edit_manifest: bool,
+    pub rustflags: Vec<String>,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub preset: Option<String>,
+    pub cargo_profile: Option<String>,
+    pub presets: BTreeMap<String, toml::Table>,
+    pub profile: toml::Table,
+    pub upx: UpxConfig,
+    pub checks: ChecksConfig,
+    pub size: SizeConfig,
+    pub hardening: HardeningConfig,
+    pub checksums: Option<Vec<Algorithm>>,
+    pub package: PackageConfig,
+    pub deb: DebConfig,
+    pub rpm: RpmConfig,
+    pub oci: OciConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UpxConfig {
+    pub enabled: bool,
+    pub args: Vec<String>,
+}
+
+impl Default for UpxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            args: vec!["--best".to_string(), "--lzma".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ChecksConfig {
+    pub clippy: bool,
+    pub deny: bool,
+    pub depcheck: bool,
+}
+
+// Size history location and the regression gates
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SizeConfig {
+    pub history_file: Option<PathBuf>,
+    pub max_size: Option<String>,
+    pub max_growth: Option<String>,
+}
+
+// Archives written by `package`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackageConfig {
+    pub formats: Vec<PackageFormat>,
+    pub dist_dir: PathBuf,
+    // Extra files; README*, LICENSE* and COPYING* when not set
+    pub include: Option<Vec<PathBuf>>,
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        Self {
+            formats: vec![PackageFormat::TarGz],
+            dist_dir: PathBuf::from(package::DEFAULT_DIST_DIR),
+            include: None,
+        }
+    }
+}
+
+impl ProjectConfig {
+    // Precedence, lowest first: `workspace.metadata` and `package.metadata` of
+    // the root manifest, `package.metadata` of the selected member, `rbt.toml`
+    pub fn load(workspace_root: &Path, member_manifests: &[&Path]) -> Result<Self, Box<dyn Error>> {
+        let mut merged = toml::Table::new();
+
+        let root_manifest = workspace_root.join("Cargo.toml");
+        for metadata in Self::read_manifest_metadata(&root_manifest, &["workspace", "package"])? {
+            merge_tables(&mut merged, metadata);
+        }
+
+        // Members disagreeing on the settings cannot share one build
+        let mut configured = Vec::new();
+        for manifest in member_manifests {
+            if *manifest == root_manifest {
+                continue;
+            }
+            for metadata in Self::read_manifest_metadata(manifest, &["package"])? {
+                configured.push((manifest, metadata));
+            }
+        }
+        if configured.len() > 1 {
+            let manifests: Vec<String> = configured
+                .iter()
+                .map(|(manifest, _)| manifest.display().to_string())
+                .collect();
+            return Err(format!(
+                "Several selected packages have their own [package.metadata.{METADATA_KEY}] ({}); build them separately",
+                manifests.join(", ")
+            )
+            .into());
+        }
+        for (_, metadata) in configured {
+            merge_tables(&mut merged, metadata);
+        }
+
+        let config_file = workspace_root.join(CONFIG_FILE);
+        if config_file.exists() {
+            let content = fs::read_to_string(&config_file)?;
+            let table: toml::Table = content
+                .parse()
+                .map_err(|e| format!("Failed to parse {CONFIG_FILE}: {e}"))?;
+            merge_tables(&mut merged, table);
+        }
+
+        toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e| format!("Invalid rust-build-tool configuration: {e}").into())
+    }
+
+    fn read_manifest_metadata(
+        cargo_toml: &Path,
+        sections: &[&str],
+    ) -> Result<Vec<toml::Table>, Box<dyn Error>> {
+        if !cargo_toml.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(cargo_toml)?;
+        let mut manifest: toml::Table = content
+            .parse()
+            .map_err(|e| format!("Failed to parse {}: {e}", cargo_toml.display()))?;
+
+        let mut tables = Vec::new();
+        for &section in sections {
+            let metadata = manifest
+                .remove(section)
+                .and_then(|mut s| s.as_table_mut()?.remove("metadata"))
+                .and_then(|mut m| m.as_table_mut()?.remove(METADATA_KEY));
+
+            match metadata {
+                Some(toml::Value::Table(table)) => tables.push(table),
+                Some(_) => {
+                    return Err(format!(
+                        "`{section}.metadata.{METADATA_KEY}` in {} must be a table",
+                        cargo_toml.display()
+                    )
+                    .into());
+                }
+                None => {}
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn panic_strategy(&self) -> PanicStrategy {
+        self.panic
+            .unwrap_or_else(|| PanicStrategy::default_for(self.mode))
+    }
+
+    pub fn preset_name(&self) -> &str {
+        self.preset
+            .as_deref()
+            .unwrap_or(cargo_config::DEFAULT_PRESET)
+    }
+
+    // Manifests written next to the artifacts; an empty list disables them
+    pub fn checksum_algorithms(&self) -> Vec<Algorithm> {
+        self.checksums
+            .clone()
+            .unwrap_or_else(|| vec![Algorithm::Sha256, Algorithm::Sha512])
+    }
+
+    pub fn cargo_profile_name(&self) -> &str {
+        self.cargo_profile
+            .as_deref()
+            .unwrap_or(cargo_config::DEFAULT_PROFILE)
+    }
+
+    // Layers, lowest first: built-in preset, panic strategy, user-defined
+    // preset, then the `profile` overrides
+    pub fn profile_settings(
+        &self,
+        preset: &str,
+        panic: PanicStrategy,
+    ) -> Result<ProfileSettings, Box<dyn Error>> {
+        let custom = self.presets.get(preset);
+        let base = match custom {
+            Some(custom) => match custom.get("inherits") {
+                Some(toml::Value::String(base)) => base.as_str(),
+                Some(_) => {
+                    return Err(format!("`inherits` of preset `{preset}` must be a string").into());
+                }
+                None => cargo_config::DEFAULT_PRESET,
+            },
+            None => preset,
+        };
+        let mut settings = cargo_config::builtin_preset(base).ok_or_else(|| {
+            let mut available: Vec<&str> = cargo_config::BUILTIN_PRESETS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect();
+            available.extend(self.presets.keys().map(String::as_str));
+            format!(
+                "Unknown preset `{base}` (available: {})",
+                available.join(", ")
+            )
+        })?;
+
+        for (key, value) in settings.iter_mut() {
+            if key == "panic" {
+                *value = format!("'{}'", panic.profile_value());
+            }
+        }
+        if let Some(custom) = custom {
+            let mut overrides = custom.clone();
+            overrides.remove("inherits");
+            cargo_config::override_settings(&mut settings, &overrides);
+        }
+        cargo_config::override_settings(&mut settings, &self.profile);
+        Ok(settings)
+    }
+
+    // Renders the configuration with every default filled in, for `config show`
+    pub fn to_effective_toml(&self) -> Result<String, Box<dyn Error>> {
+        let mut effective = self.clone();
+        effective.panic = Some(self.panic_strategy());
+        effective.preset = Some(self.preset_name().to_string());
+        effective.cargo_profile = Some(self.cargo_profile_name().to_string());
+        effective.checksums = Some(self.checksum_algorithms());
+        effective.profile = toml::Table::new();
+        for (key, literal) in self.profile_settings(self.preset_name(), self.panic_strategy())? {
+            let value: toml::Table = format!("v = {literal}").parse()?;
+            if let Some(value) = value.get("v") {
+                effective.profile.insert(key, value.clone());
+            }
+        }
+        Ok(toml::to_string(&effective)?)
+    }
+}
+
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(overlay)) => {
+                merge_tables(existing, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fresh directory holding `Cargo.toml` and, when given, `rbt.toml`
+    fn project(name: &str, manifest: &str, rbt: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rbt-config-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        if let Some(rbt) = rbt {
+            fs::write(dir.join(CONFIG_FILE), rbt).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn rbt_toml_wins_over_package_and_workspace_metadata() {
+        let dir = project(
+            "precedence",
+            r#"
+[workspace.metadata.rust-build-tool]
+mode = "stable"
+keep-going = true
+preset = "speed"
+targets = ["aarch64-unknown-linux-gnu"]
+
+[package]
+name = "app"
+
+[package.metadata.rust-build-tool]
+preset = "balanced"
+targets = ["x86_64-unknown-linux-musl"]
+"#,
+            Some("targets = [\"x86_64-unknown-linux-gnu\"]\n"),
+        );
+        let config = ProjectConfig::load(&dir, &[]).unwrap();
+        assert_eq!(config.mode, BuildMode::Stable);
+        assert!(config.keep_going);
+        assert_eq!(config.preset_name(), "balanced");
+        assert_eq!(config.targets, ["x86_64-unknown-linux-gnu"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nested_tables_merge_key_by_key() {
+        let dir = project(
+            "nested",
+            r#"
+[package]
+name = "app"
+
+[package.metadata.rust-build-tool.upx]
+enabled = true
+args = ["--best"]
+
+[package.metadata.rust-build-tool.profile]
+opt-level = 3
+debug = true
+"#,
+            Some("[upx]\nargs = [\"-9\"]\n\n[profile]\nopt-level = 2\n"),
+        );
+        let config = ProjectConfig::load(&dir, &[]).unwrap();
+        assert!(config.upx.enabled);
+        assert_eq!(config.upx.args, ["-9"]);
+        assert_eq!(config.profile.get("opt-level"), Some(&toml::Value::from(2)));
+        assert_eq!(config.profile.get("debug"), Some(&toml::Value::from(true)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let dir = project(
+            "unknown",
+            "[package]\nname = \"app\"\n",
+            Some("[upx]\nenable = true\n"),
+        );
+        let error = ProjectConfig::load(&dir, &[]).unwrap_err().to_string();
+        assert!(error.contains("unknown field `enable`"), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn metadata_must_be_a_table() {
+        let dir = project(
+            "not-table",
+            "[package]\nname = \"app\"\n\n[package.metadata]\nrust-build-tool = \"size\"\n",
+            None,
+        );
+        let error = ProjectConfig::load(&dir, &[]).unwrap_err().to_string();
+        assert!(
+            error.starts_with("`package.metadata.rust-build-tool` in "),
+            "{error}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn selected_members_override_the_root_manifest() {
+        let dir = project(
+            "members",
+            r#"
+[workspace]
+members = ["app", "tool"]
+
+[workspace.metadata.rust-build-tool]
+preset = "speed"
+keep-going = true
+"#,
+            Some("targets = [\"x86_64-unknown-linux-musl\"]\n"),
+        );
+        for (member, preset) in [("app", "balanced"), ("tool", "size")] {
+            fs::create_dir_all(dir.join(member)).unwrap();
+            fs::write(
+                dir.join(member).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{member}\"\n\n[package.metadata.rust-build-tool]\npreset = \"{preset}\"\ntargets = [\"aarch64-unknown-linux-gnu\"]\n"
+                ),
+            )
+            .unwrap();
+        }
+        let app = dir.join("app/Cargo.toml");
+        let tool = dir.join("tool/Cargo.toml");
+
+        let config = ProjectConfig::load(&dir, &[&app]).unwrap();
+        assert_eq!(config.preset_name(), "balanced");
+        assert!(config.keep_going);
+        assert_eq!(config.targets, ["x86_64-unknown-linux-musl"]);
+        assert_eq!(
+            ProjectConfig::load(&dir, &[]).unwrap().preset_name(),
+            "speed"
+        );
+
+        let error = ProjectConfig::load(&dir, &[&app, &tool])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("build them separately"), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn config(content: &str) -> ProjectConfig {
+        toml::from_str(content).unwrap()
+    }
+
+    fn setting<'a>(settings: &'a ProfileSettings, key: &str) -> Option<&'a str> {
+        settings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn builtin_preset_takes_the_panic_strategy() {
+        let settings = ProjectConfig::default()
+            .profile_settings("speed", PanicStrategy::Unwind)
+            .unwrap();
+        assert_eq!(setting(&settings, "opt-level"), Some("3"));
+        assert_eq!(setting(&settings, "panic"), Some("'unwind'"));
+
+        let settings = ProjectConfig::default()
+            .profile_settings("size", PanicStrategy::ImmediateAbort)
+            .unwrap();
+        assert_eq!(setting(&settings, "panic"), Some("'abort'"));
+    }
+
+    #[test]
+    fn custom_presets_inherit_and_profile_overrides_win() {
+        let config = config(
+            r#"
+[presets.tiny]
+inherits = "speed"
+opt-level = "s"
+debug = 1
+
+[presets.plain]
+strip = false
+
+[profile]
+debug = 2
+"#,
+        );
+        let settings = config
+            .profile_settings("tiny", PanicStrategy::Abort)
+            .unwrap();
+        // From `speed`, then `tiny`, then `profile`
+        assert_eq!(setting(&settings, "lto"), Some("'fat'"));
+        assert_eq!(setting(&settings, "opt-level"), Some("\"s\""));
+        assert_eq!(setting(&settings, "debug"), Some("2"));
+        assert_eq!(setting(&settings, "inherits"), None);
+
+        // Without `inherits`, custom presets build on the default preset
+        let settings = config
+            .profile_settings("plain", PanicStrategy::Abort)
+            .unwrap();
+        assert_eq!(setting(&settings, "opt-level"), Some("'z'"));
+        assert_eq!(setting(&settings, "strip"), Some("false"));
+    }
+
+    #[test]
+    fn unknown_presets_and_bad_inherits_are_errors() {
+        let config =
+            config("[presets.broken]\ninherits = 3\n\n[presets.orphan]\ninherits = \"huge\"\n");
+        assert_eq!(
+            config
+                .profile_settings("missing", PanicStrategy::Abort)
+                .unwrap_err()
+                .to_string(),
+            "Unknown preset `missing` (available: size, speed, balanced, debuggable-release, broken, orphan)"
+        );
+        assert_eq!(
+            config
+                .profile_settings("broken", PanicStrategy::Abort)
+                .unwrap_err()
+                .to_string(),
+            "`inherits` of preset `broken` must be a string"
+        );
+        assert!(
+            config
+                .profile_settings("orphan", PanicStrategy::Abort)
+                .unwrap_err()
+                .to_string()
+                .starts_with("Unknown preset `huge`")
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::artifact::Artifact;
+use crate::build_system::BuildMode;
+use crate::checksum::{self, Algorithm};
+use crate::hardening::Hardening;
+
+// `--report json` document, written once every step has finished
+#[derive(Debug, Serialize)]
+pub struct BuildReport {
+    pub tool_version: String,
+    pub toolchain: Toolchain,
+    pub mode: BuildMode,
+    pub profile: String,
+    pub profile_settings: BTreeMap<String, toml::Value>,
+    pub success: bool,
+    pub steps: Vec<StepReport>,
+    pub targets: Vec<TargetReport>,
+}
+
+impl BuildReport {
+    pub fn write(&self, path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_string_pretty(self)?;
+        match path {
+            Some(path) => {
+                fs::write(path, json + "\n")
+                    .map_err(|e| format!("Failed to write report {}: {e}", path.display()))?;
+                println!("Build report written to {}", path.display());
+            }
+            None => println!("{json}"),
+        }
+        Ok(())
+    }
+}
+
+// While the report is bound for stdout, everything else written there, by us
+// or by cargo and the other tools we run, goes to stderr so that the JSON can
+// be piped. Restored on drop.
+pub struct StdoutToStderr {
+    saved: libc::c_int,
+}
+
+impl StdoutToStderr {
+    pub fn redirect() -> Result<Self, Box<dyn Error>> {
+        io::stdout().flush()?;
+        // SAFETY: plain descriptor duplication on the standard streams
+        let saved = unsafe { libc::dup(1) };
+        if saved < 0 || unsafe { libc::dup2(2, 1) } < 0 {
+            return Err(
+                format!("Failed to redirect stdout: {}", io::Error::last_os_error()).into(),
+            );
+        }
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        // SAFETY: `saved` is the descriptor duplicated in `redirect`
+        unsafe {
+            libc::dup2(self.saved, 1);
+            libc::close(self.saved);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Toolchain {
+    pub rustc: Option<String>,
+    pub cargo: Option<String>,
+}
+
+impl Toolchain {
+    // `toolchain` is the rustup override, e.g. `+nightly`
+    pub fn detect(toolchain: &str) -> Self {
+        Self {
+            rustc: tool_version("rustc", toolchain),
+            cargo: tool_version("cargo", toolchain),
+        }
+    }
+}
+
+fn tool_version(tool: &str, toolchain: &str) -> Option<String> {
+    let output = Command::new(tool).args([toolchain, "-V"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepReport {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub status: StepStatus,
+    pub duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl StepReport {
+    pub fn new(
+        name: &str,
+        target: Option<&str>,
+        duration: Duration,
+        result: Result<(), String>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            target: target.map(str::to_string),
+            status: if result.is_ok() {
+                StepStatus::Passed
+            } else {
+                StepStatus::Failed
+            },
+            duration_ms: duration.as_millis(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetReport {
+    pub target: String,
+    pub status: StepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub artifacts: Vec<ArtifactReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtifactReport {
+    pub package: String,
+    pub name: String,
+    pub kind: String,
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_before_upx: Option<u64>,
+    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardening: Option<Hardening>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardening_before_upx: Option<Hardening>,
+}
+
+impl ArtifactReport {
+    pub fn from_artifact(artifact: &Artifact) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            package: artifact.package.clone(),
+            name: artifact.name.clone(),
+            kind: artifact.kind.name().to_string(),
+            path: artifact.path.clone(),
+            size: artifact.path.metadata()?.len(),
+            size_before_upx: artifact.size_before_upx,
+            sha256: checksum::file_digest(&artifact.path, Algorithm::Sha256)?,
+            hardening: artifact.hardening.clone(),
+            hardening_before_upx: artifact.hardening_before_upx.clone(),
+        })
+    }
+}
+
+// Profile literals such as `'z'` or `true` as typed values
+pub fn profile_values(settings: &[(String, String)]) -> BTreeMap<String, toml::Value> {
+    settings
+        .iter()
+        .filter_map(|(key, literal)| {
+            let table: toml::Table = format!("v = {literal}").parse().ok()?;
+            Some((key.clone(), table.get("v")?.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_literals_become_typed_values() {
+        let settings = vec![
+            ("opt-level".to_string(), "'z'".to_string()),
+            ("codegen-units".to_string(), "1".to_string()),
+            ("lto".to_string(), "true".to_string()),
+        ];
+        let json = serde_json::to_value(profile_values(&settings)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"opt-level": "z", "codegen-units": 1, "lto": true})
+        );
+    }
+
+    #[test]
+    fn failed_steps_carry_their_error() {
+        let step = StepReport::new(
+            "build",
+            Some("x86_64-unknown-linux-gnu"),
+            Duration::from_millis(1500),
+            Err("linker not found".to_string()),
+        );
+        assert_eq!(
+            serde_json::to_value(step).unwrap(),
+            serde_json::json!({
+                "name": "build",
+                "target": "x86_64-unknown-linux-gnu",
+                "status": "failed",
+                "duration_ms": 1500,
+                "error": "linker not found"
+            })
+        );
+    }
+}
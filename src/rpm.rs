@@ -0,0 +1,690 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::package::{self, Asset, PackageInput, Source};
+use crate::workspace::WorkspaceMember;
+
+// `[rpm]` section of the project configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RpmConfig {
+    pub release: String,
+    // Defaults to the first of the package's `authors`
+    pub packager: Option<String>,
+    pub group: String,
+    // `name` or `name >= version`
+    pub requires: Vec<String>,
+    pub assets: Vec<Asset>,
+}
+
+impl Default for RpmConfig {
+    fn default() -> Self {
+        Self {
+            release: "1".to_string(),
+            packager: None,
+            group: "Unspecified".to_string(),
+            requires: Vec::new(),
+            assets: Vec::new(),
+        }
+    }
+}
+
+// Header tag numbers from rpm's rpmtag.h
+const HEADER_SIGNATURES: u32 = 62;
+const HEADER_IMMUTABLE: u32 = 63;
+const HEADER_I18NTABLE: u32 = 100;
+const SIG_SHA256: u32 = 273;
+const SIG_SIZE: u32 = 1000;
+const SIG_PAYLOADSIZE: u32 = 1007;
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_SUMMARY: u32 = 1004;
+const TAG_DESCRIPTION: u32 = 1005;
+const TAG_BUILDTIME: u32 = 1006;
+const TAG_BUILDHOST: u32 = 1007;
+const TAG_SIZE: u32 = 1009;
+const TAG_LICENSE: u32 = 1014;
+const TAG_PACKAGER: u32 = 1015;
+const TAG_GROUP: u32 = 1016;
+const TAG_URL: u32 = 1020;
+const TAG_OS: u32 = 1021;
+const TAG_ARCH: u32 = 1022;
+const TAG_FILESIZES: u32 = 1028;
+const TAG_FILEMODES: u32 = 1030;
+const TAG_FILERDEVS: u32 = 1033;
+const TAG_FILEMTIMES: u32 = 1034;
+const TAG_FILEDIGESTS: u32 = 1035;
+const TAG_FILELINKTOS: u32 = 1036;
+const TAG_FILEFLAGS: u32 = 1037;
+const TAG_FILEUSERNAME: u32 = 1039;
+const TAG_FILEGROUPNAME: u32 = 1040;
+const TAG_SOURCERPM: u32 = 1044;
+const TAG_PROVIDENAME: u32 = 1047;
+const TAG_REQUIREFLAGS: u32 = 1048;
+const TAG_REQUIRENAME: u32 = 1049;
+const TAG_REQUIREVERSION: u32 = 1050;
+const TAG_FILEDEVICES: u32 = 1095;
+const TAG_FILEINODES: u32 = 1096;
+const TAG_FILELANGS: u32 = 1097;
+const TAG_PROVIDEFLAGS: u32 = 1112;
+const TAG_PROVIDEVERSION: u32 = 1113;
+const TAG_DIRINDEXES: u32 = 1116;
+const TAG_BASENAMES: u32 = 1117;
+const TAG_DIRNAMES: u32 = 1118;
+const TAG_PAYLOADFORMAT: u32 = 1124;
+const TAG_PAYLOADCOMPRESSOR: u32 = 1125;
+const TAG_PAYLOADFLAGS: u32 = 1126;
+const TAG_FILEDIGESTALGO: u32 = 5011;
+const TAG_PAYLOADDIGEST: u32 = 5092;
+const TAG_PAYLOADDIGESTALGO: u32 = 5093;
+
+const TYPE_INT16: u32 = 3;
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_BIN: u32 = 7;
+const TYPE_STRING_ARRAY: u32 = 8;
+const TYPE_I18NSTRING: u32 = 9;
+
+const DIGEST_SHA256: u32 = 8;
+const SENSE_LESS: u32 = 1 << 1;
+const SENSE_GREATER: u32 = 1 << 2;
+const SENSE_EQUAL: u32 = 1 << 3;
+const SENSE_RPMLIB: u32 = 1 << 24;
+const FILE_CONFIG: u32 = 1;
+const FILE_DOC: u32 = 1 << 1;
+const FILE_NOREPLACE: u32 = 1 << 4;
+
+// `<name>-<version>-<release>.<arch>.rpm`, as rpmbuild names them
+pub fn file_name(
+    member: &WorkspaceMember,
+    target: &str,
+    config: &RpmConfig,
+) -> Result<String, Box<dyn Error>> {
+    Ok(format!(
+        "{}-{}-{}.{}.rpm",
+        member.name,
+        version(&member.version),
+        config.release,
+        architecture(target)?
+    ))
+}
+
+// RPM architecture of a Linux target triple
+pub fn architecture(target: &str) -> Result<&'static str, String> {
+    let unsupported = || format!("No RPM architecture for target `{target}`");
+    if !target.contains("-linux") {
+        return Err(unsupported());
+    }
+    let arch = target.split('-').next().unwrap_or_default();
+    Ok(match arch {
+        "x86_64" => "x86_64",
+        "i586" => "i586",
+        "i686" => "i686",
+        "aarch64" => "aarch64",
+        "armv7" | "thumbv7neon" => {
+            if target.ends_with("hf") {
+                "armv7hl"
+            } else {
+                "armv7l"
+            }
+        }
+        "arm" | "armv6" => {
+            if target.ends_with("hf") {
+                "armv6hl"
+            } else {
+                "armv6l"
+            }
+        }
+        "powerpc64" => "ppc64",
+        "powerpc64le" => "ppc64le",
+        "riscv64gc" => "riscv64",
+        "s390x" => "s390x",
+        "loongarch64" => "loongarch64",
+        _ => return Err(unsupported()),
+    })
+}
+
+// `-` separates version and release, so a pre-release becomes `1.0.0~beta.1`
+fn version(version: &str) -> String {
+    version.replacen('-', "~", 1).replace('-', "_")
+}
+
+// `name`, or `name <op> version` with op one of < <= = >= >
+fn parse_requirement(requirement: &str) -> Result<(String, u32, String), String> {
+    let parts: Vec<&str> = requirement.split_whitespace().collect();
+    match parts[..] {
+        [name] => Ok((name.to_string(), 0, String::new())),
+        [name, op, version] => {
+            let flags = match op {
+                "<" => SENSE_LESS,
+                "<=" => SENSE_LESS | SENSE_EQUAL,
+                "=" => SENSE_EQUAL,
+                ">=" => SENSE_GREATER | SENSE_EQUAL,
+                ">" => SENSE_GREATER,
+                _ => {
+                    return Err(format!(
+                        "Invalid operator in RPM requirement `{requirement}`"
+                    ));
+                }
+            };
+            Ok((name.to_string(), flags, version.to_string()))
+        }
+        _ => Err(format!(
+            "Invalid RPM requirement `{requirement}` (expected e.g. `openssl-libs >= 1.1`)"
+        )),
+    }
+}
+
+struct PayloadFile {
+    path: String,
+    mode: u32,
+    data: Vec<u8>,
+    flags: u32,
+}
+
+pub fn write(
+    mut file: File,
+    input: &PackageInput,
+    config: &RpmConfig,
+) -> Result<(), Box<dyn Error>> {
+    let member = input.member;
+    let arch = architecture(input.target)?;
+    let version = version(&member.version);
+    let release = &config.release;
+
+    // Directories are left to the packages owning them (filesystem, ...)
+    let tree = package::install_tree(input, &member.name, &config.assets, &[], "")?;
+    let mut files = Vec::new();
+    for entry in tree {
+        if matches!(entry.source, Source::Directory) {
+            continue;
+        }
+        let flags = if entry.path.starts_with("etc/") {
+            FILE_CONFIG | FILE_NOREPLACE
+        } else if entry.path.starts_with("usr/share/doc/") {
+            FILE_DOC
+        } else {
+            0
+        };
+        files.push(PayloadFile {
+            path: format!("/{}", entry.path),
+            mode: 0o100000 | entry.mode,
+            data: entry.read()?,
+            flags,
+        });
+    }
+
+    let cpio = cpio(&files, input.mtime);
+    let mut encoder = flate2::GzBuilder::new()
+        .mtime(0)
+        .write(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&cpio)?;
+    let payload = encoder.finish()?;
+
+    let mut header = Header::default();
+    header.strings(HEADER_I18NTABLE, &["C".to_string()]);
+    header.string(TAG_NAME, &member.name);
+    header.string(TAG_VERSION, &version);
+    header.string(TAG_RELEASE, release);
+    let description = member.description.as_deref().unwrap_or(&member.name).trim();
+    header.i18n(
+        TAG_SUMMARY,
+        description.lines().next().unwrap_or_default().trim(),
+    );
+    header.i18n(TAG_DESCRIPTION, description);
+    header.int32(TAG_BUILDTIME, &[input.mtime as u32]);
+    header.string(TAG_BUILDHOST, "localhost");
+    header.int32(
+        TAG_SIZE,
+        &[files.iter().map(|f| f.data.len() as u32).sum::<u32>()],
+    );
+    header.string(
+        TAG_LICENSE,
+        member.license.as_deref().unwrap_or("Unspecified"),
+    );
+    if let Some(packager) = config.packager.as_ref().or(member.authors.first()) {
+        header.string(TAG_PACKAGER, packager);
+    }
+    header.i18n(TAG_GROUP, &config.group);
+    if let Some(url) = member.homepage.as_ref().or(member.repository.as_ref()) {
+        header.string(TAG_URL, url);
+    }
+    header.string(TAG_OS, "linux");
+    header.string(TAG_ARCH, arch);
+    header.string(
+        TAG_SOURCERPM,
+        &format!("{}-{version}-{release}.src.rpm", member.name),
+    );
+
+    header.strings(TAG_PROVIDENAME, std::slice::from_ref(&member.name));
+    header.int32(TAG_PROVIDEFLAGS, &[SENSE_EQUAL]);
+    header.strings(TAG_PROVIDEVERSION, &[format!("{version}-{release}")]);
+    let mut requires = vec![
+        (
+            "rpmlib(CompressedFileNames)".to_string(),
+            SENSE_RPMLIB | SENSE_LESS | SENSE_EQUAL,
+            "3.0.4-1".to_string(),
+        ),
+        (
+            "rpmlib(FileDigests)".to_string(),
+            SENSE_RPMLIB | SENSE_LESS | SENSE_EQUAL,
+            "4.6.0-1".to_string(),
+        ),
+        (
+            "rpmlib(PayloadFilesHavePrefix)".to_string(),
+            SENSE_RPMLIB | SENSE_LESS | SENSE_EQUAL,
+            "4.0-1".to_string(),
+        ),
+    ];
+    for requirement in &config.requires {
+        requires.push(parse_requirement(requirement)?);
+    }
+    header.strings(
+        TAG_REQUIRENAME,
+        &requires.iter().map(|r| r.0.clone()).collect::<Vec<_>>(),
+    );
+    header.int32(
+        TAG_REQUIREFLAGS,
+        &requires.iter().map(|r| r.1).collect::<Vec<_>>(),
+    );
+    header.strings(
+        TAG_REQUIREVERSION,
+        &requires.iter().map(|r| r.2.clone()).collect::<Vec<_>>(),
+    );
+
+    // File list, in the same order as the payload
+    let mut dirnames: Vec<String> = Vec::new();
+    let mut dirindexes = Vec::new();
+    let mut basenames = Vec::new();
+    for file in &files {
+        let (dir, base) = file.path.rsplit_once('/').unwrap_or_default();
+        let dir = format!("{dir}/");
+        let index = match dirnames.iter().position(|d| *d == dir) {
+            Some(index) => index,
+            None => {
+                dirnames.push(dir);
+                dirnames.len() - 1
+            }
+        };
+        dirindexes.push(index as u32);
+        basenames.push(base.to_string());
+    }
+    let count = files.len();
+    header.int32(
+        TAG_FILESIZES,
+        &files
+            .iter()
+            .map(|f| f.data.len() as u32)
+            .collect::<Vec<_>>(),
+    );
+    header.int16(
+        TAG_FILEMODES,
+        &files.iter().map(|f| f.mode as u16).collect::<Vec<_>>(),
+    );
+    header.int16(TAG_FILERDEVS, &vec![0; count]);
+    header.int32(TAG_FILEMTIMES, &vec![input.mtime as u32; count]);
+    header.strings(
+        TAG_FILEDIGESTS,
+        &files
+            .iter()
+            .map(|f| format!("{:x}", Sha256::digest(&f.data)))
+            .collect::<Vec<_>>(),
+    );
+    header.strings(TAG_FILELINKTOS, &vec![String::new(); count]);
+    header.int32(
+        TAG_FILEFLAGS,
+        &files.iter().map(|f| f.flags).collect::<Vec<_>>(),
+    );
+    header.strings(TAG_FILEUSERNAME, &vec!["root".to_string(); count]);
+    header.strings(TAG_FILEGROUPNAME, &vec!["root".to_string(); count]);
+    header.int32(TAG_FILEDEVICES, &vec![1; count]);
+    header.int32(TAG_FILEINODES, &(1..=count as u32).collect::<Vec<_>>());
+    header.strings(TAG_FILELANGS, &vec![String::new(); count]);
+    header.int32(TAG_DIRINDEXES, &dirindexes);
+    header.strings(TAG_BASENAMES, &basenames);
+    header.strings(TAG_DIRNAMES, &dirnames);
+    header.string(TAG_PAYLOADFORMAT, "cpio");
+    header.string(TAG_PAYLOADCOMPRESSOR, "gzip");
+    header.string(TAG_PAYLOADFLAGS, "9");
+    header.int32(TAG_FILEDIGESTALGO, &[DIGEST_SHA256]);
+    header.strings(
+        TAG_PAYLOADDIGEST,
+        &[format!("{:x}", Sha256::digest(&payload))],
+    );
+    header.int32(TAG_PAYLOADDIGESTALGO, &[DIGEST_SHA256]);
+    let header = header.into_bytes(HEADER_IMMUTABLE);
+
+    let mut signature = Header::default();
+    signature.string(SIG_SHA256, &format!("{:x}", Sha256::digest(&header)));
+    signature.int32(SIG_SIZE, &[(header.len() + payload.len()) as u32]);
+    signature.int32(SIG_PAYLOADSIZE, &[cpio.len() as u32]);
+    let mut signature = signature.into_bytes(HEADER_SIGNATURES);
+    signature.resize(signature.len().next_multiple_of(8), 0);
+
+    let nvr = format!("{}-{version}-{release}", member.name);
+    file.write_all(&lead(&nvr))?;
+    file.write_all(&signature)?;
+    file.write_all(&header)?;
+    file.write_all(&payload)?;
+    Ok(())
+}
+
+// Fixed 96-byte preamble; rpm only checks its magic and type nowadays
+fn lead(nvr: &str) -> Vec<u8> {
+    let mut lead = vec![0xed, 0xab, 0xee, 0xdb, 3, 0];
+    lead.extend_from_slice(&0u16.to_be_bytes()); // binary package
+    lead.extend_from_slice(&0u16.to_be_bytes()); // architecture, unused
+    let mut name = [0u8; 66];
+    let len = nvr.len().min(65);
+    name[..len].copy_from_slice(&nvr.as_bytes()[..len]);
+    lead.extend_from_slice(&name);
+    lead.extend_from_slice(&1u16.to_be_bytes()); // Linux
+    lead.extend_from_slice(&5u16.to_be_bytes()); // header-style signature
+    lead.resize(96, 0);
+    lead
+}
+
+// `newc` cpio archive with `./`-prefixed names, as rpm expects
+fn cpio(files: &[PayloadFile], mtime: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        cpio_entry(
+            &mut out,
+            &format!(".{}", file.path),
+            index as u32 + 1,
+            file.mode,
+            mtime,
+            &file.data,
+        );
+    }
+    cpio_entry(&mut out, "TRAILER!!!", 0, 0, 0, &[]);
+    out
+}
+
+fn cpio_entry(out: &mut Vec<u8>, name: &str, inode: u32, mode: u32, mtime: u64, data: &[u8]) {
+    let nlink = u32::from(mode != 0 || name == "TRAILER!!!");
+    out.extend_from_slice(
+        format!(
+            "070701{inode:08x}{mode:08x}{:08x}{:08x}{nlink:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0,
+            0,
+            mtime as u32,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0
+        )
+        .as_bytes(),
+    );
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.resize(out.len().next_multiple_of(4), 0);
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+// An rpm header structure: an index of typed entries over a data store
+#[derive(Default)]
+struct Header {
+    entries: Vec<(u32, u32, u32, Vec<u8>)>,
+}
+
+impl Header {
+    fn string(&mut self, tag: u32, value: &str) {
+        self.entries
+            .push((tag, TYPE_STRING, 1, nul_terminated(value)));
+    }
+
+    fn i18n(&mut self, tag: u32, value: &str) {
+        self.entries
+            .push((tag, TYPE_I18NSTRING, 1, nul_terminated(value)));
+    }
+
+    fn strings(&mut self, tag: u32, values: &[String]) {
+        let data = values.iter().flat_map(|v| nul_terminated(v)).collect();
+        self.entries
+            .push((tag, TYPE_STRING_ARRAY, values.len() as u32, data));
+    }
+
+    fn int32(&mut self, tag: u32, values: &[u32]) {
+        let data = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        self.entries
+            .push((tag, TYPE_INT32, values.len() as u32, data));
+    }
+
+    fn int16(&mut self, tag: u32, values: &[u16]) {
+        let data = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        self.entries
+            .push((tag, TYPE_INT16, values.len() as u32, data));
+    }
+
+    // The region tag comes first in the index and its trailer closes the data
+    fn into_bytes(mut self, region_tag: u32) -> Vec<u8> {
+        self.entries.sort_by_key(|(tag, ..)| *tag);
+        let count = self.entries.len() as u32 + 1;
+
+        let mut index = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        for (tag, kind, entry_count, value) in &self.entries {
+            let align = match *kind {
+                TYPE_INT16 => 2,
+                TYPE_INT32 => 4,
+                _ => 1,
+            };
+            data.resize(data.len().next_multiple_of(align), 0);
+            index.push((*tag, *kind, data.len() as u32, *entry_count));
+            data.extend_from_slice(value);
+        }
+        let trailer_offset = data.len() as u32;
+        for value in [region_tag, TYPE_BIN, (-(count as i32 * 16)) as u32, 16] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        index.insert(0, (region_tag, TYPE_BIN, trailer_offset, 16));
+
+        let mut out = vec![0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0];
+        out.extend_from_slice(&count.to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        for (tag, kind, offset, entry_count) in index {
+            for value in [tag, kind, offset, entry_count] {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&data);
+        out
+    }
+}
+
+fn nul_terminated(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triples_map_to_rpm_architectures() {
+        assert_eq!(architecture("x86_64-unknown-linux-musl"), Ok("x86_64"));
+        assert_eq!(architecture("armv7-unknown-linux-gnueabihf"), Ok("armv7hl"));
+        assert_eq!(architecture("powerpc64le-unknown-linux-gnu"), Ok("ppc64le"));
+        assert!(architecture("aarch64-apple-darwin").is_err());
+        assert_eq!(version("1.0.0-rc-1"), "1.0.0~rc_1");
+    }
+
+    #[test]
+    fn requirements_parse_with_optional_version() {
+        assert_eq!(
+            parse_requirement("openssl-libs >= 1.1").unwrap(),
+            (
+                "openssl-libs".to_string(),
+                SENSE_GREATER | SENSE_EQUAL,
+                "1.1".to_string()
+            )
+        );
+        assert_eq!(parse_requirement("glibc").unwrap().1, 0);
+        assert!(parse_requirement("glibc ~ 2").is_err());
+    }
+
+    struct ParsedHeader {
+        // (tag, type, offset, count), region first
+        index: Vec<(u32, u32, u32, u32)>,
+        data: Vec<u8>,
+        len: usize,
+    }
+
+    fn be32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // Reads a header structure back, checking its region and alignment
+    fn parse_header(bytes: &[u8], region_tag: u32) -> ParsedHeader {
+        assert_eq!(bytes[..8], [0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0]);
+        let count = be32(bytes, 8) as usize;
+        let size = be32(bytes, 12) as usize;
+        let index: Vec<_> = (0..count)
+            .map(|i| {
+                let at = 16 + i * 16;
+                (
+                    be32(bytes, at),
+                    be32(bytes, at + 4),
+                    be32(bytes, at + 8),
+                    be32(bytes, at + 12),
+                )
+            })
+            .collect();
+        let start = 16 + count * 16;
+        let data = bytes[start..start + size].to_vec();
+
+        assert_eq!(index[0], (region_tag, TYPE_BIN, size as u32 - 16, 16));
+        let trailer: Vec<u32> = (0..4).map(|i| be32(&data, size - 16 + i * 4)).collect();
+        assert_eq!(
+            trailer,
+            [region_tag, TYPE_BIN, (-(count as i32 * 16)) as u32, 16]
+        );
+        for &(tag, kind, offset, _) in &index[1..] {
+            let align = match kind {
+                TYPE_INT16 => 2,
+                TYPE_INT32 => 4,
+                _ => 1,
+            };
+            assert_eq!(offset % align, 0, "tag {tag} is aligned");
+            assert!((offset as usize) < size - 16);
+        }
+        ParsedHeader {
+            index,
+            data,
+            len: start + size,
+        }
+    }
+
+    impl ParsedHeader {
+        fn entry(&self, tag: u32) -> (u32, usize, u32) {
+            let (_, kind, offset, count) = self.index.iter().find(|e| e.0 == tag).unwrap();
+            (*kind, *offset as usize, *count)
+        }
+
+        fn string(&self, tag: u32) -> String {
+            let (kind, offset, _) = self.entry(tag);
+            assert!(matches!(
+                kind,
+                TYPE_STRING | TYPE_STRING_ARRAY | TYPE_I18NSTRING
+            ));
+            let end = offset + self.data[offset..].iter().position(|&b| b == 0).unwrap();
+            String::from_utf8(self.data[offset..end].to_vec()).unwrap()
+        }
+
+        fn int32(&self, tag: u32) -> u32 {
+            let (kind, offset, count) = self.entry(tag);
+            assert_eq!((kind, count), (TYPE_INT32, 1));
+            be32(&self.data, offset)
+        }
+    }
+
+    #[test]
+    fn headers_describe_the_payload() {
+        let dir = std::env::temp_dir().join(format!("rbt-rpm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app"), b"ELF").unwrap();
+        let member = WorkspaceMember {
+            name: "app".to_string(),
+            version: "1.2.0".to_string(),
+            description: Some("Does things".to_string()),
+            license: Some("MIT".to_string()),
+            authors: vec!["Jane Doe <jane@example.com>".to_string()],
+            homepage: None,
+            repository: None,
+            manifest_path: dir.join("Cargo.toml"),
+            bin_targets: vec!["app".to_string()],
+            example_targets: Vec::new(),
+        };
+        let input = PackageInput {
+            member: &member,
+            target: "aarch64-unknown-linux-gnu",
+            workspace_root: &dir,
+            files: vec![package::PackageFile {
+                source: dir.join("app"),
+                name: "app".to_string(),
+                kind: package::FileKind::Executable,
+            }],
+            mtime: 1_700_000_000,
+        };
+        let config = RpmConfig {
+            requires: vec!["glibc >= 2.28".to_string()],
+            ..Default::default()
+        };
+        let path = dir.join("app.rpm");
+        write(File::create(&path).unwrap(), &input, &config).unwrap();
+        let rpm = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(rpm[..6], [0xed, 0xab, 0xee, 0xdb, 3, 0]);
+        assert!(rpm[10..].starts_with(b"app-1.2.0-1\0"));
+        let signature = parse_header(&rpm[96..], HEADER_SIGNATURES);
+        assert_eq!(signature.index.len(), 4);
+        let header_start = (96 + signature.len).next_multiple_of(8);
+        assert!(
+            rpm[96 + signature.len..header_start]
+                .iter()
+                .all(|&b| b == 0)
+        );
+        let header = parse_header(&rpm[header_start..], HEADER_IMMUTABLE);
+        let header_bytes = &rpm[header_start..header_start + header.len];
+        let payload = &rpm[header_start + header.len..];
+
+        assert_eq!(
+            signature.string(SIG_SHA256),
+            format!("{:x}", Sha256::digest(header_bytes))
+        );
+        assert_eq!(
+            signature.int32(SIG_SIZE) as usize,
+            header.len + payload.len()
+        );
+        let mut cpio = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(payload), &mut cpio).unwrap();
+        assert_eq!(signature.int32(SIG_PAYLOADSIZE) as usize, cpio.len());
+        assert_eq!(
+            header.string(TAG_PAYLOADDIGEST),
+            format!("{:x}", Sha256::digest(payload))
+        );
+        assert!(cpio.starts_with(b"070701"));
+        assert!(cpio.windows(14).any(|w| w == b"./usr/bin/app\0"));
+        assert!(cpio.windows(11).any(|w| w == b"TRAILER!!!\0"));
+
+        assert_eq!(header.string(TAG_NAME), "app");
+        assert_eq!(header.string(TAG_ARCH), "aarch64");
+        assert_eq!(header.string(TAG_BASENAMES), "app");
+        assert_eq!(header.string(TAG_DIRNAMES), "/usr/bin/");
+        assert_eq!(header.entry(TAG_REQUIRENAME).2, 4);
+        assert_eq!(header.int32(TAG_SIZE), 3);
+    }
+}
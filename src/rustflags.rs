@@ -0,0 +1,450 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Flags the user already configured for cargo, found the way cargo itself
+// resolves them: CARGO_ENCODED_RUSTFLAGS, RUSTFLAGS, target.<triple>.rustflags
+// together with the matching target.'cfg(...)'.rustflags, then
+// build.rustflags. The first source that is set wins.
+#[derive(Debug, Clone, Default)]
+pub struct UserRustflags {
+    pub flags: Vec<String>,
+    pub source: String,
+}
+
+impl UserRustflags {
+    pub fn resolve(target: &str, toolchain: &str) -> Result<Self, Box<dyn Error>> {
+        Self::resolve_in(
+            &env::current_dir()?,
+            &|key| env::var(key).ok(),
+            target,
+            &|| target_cfg(target, toolchain),
+        )
+    }
+
+    // `var` reads the environment and `cfg` lists the target's cfg values,
+    // only needed when a config file has `cfg(...)` tables
+    fn resolve_in(
+        cwd: &Path,
+        var: &dyn Fn(&str) -> Option<String>,
+        target: &str,
+        cfg: &dyn Fn() -> Result<Vec<Cfg>, Box<dyn Error>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Set but empty still means "no flags" to cargo
+        if let Some(encoded) = var("CARGO_ENCODED_RUSTFLAGS") {
+            return Ok(Self {
+                flags: if encoded.is_empty() {
+                    Vec::new()
+                } else {
+                    encoded.split('\x1f').map(str::to_string).collect()
+                },
+                source: "CARGO_ENCODED_RUSTFLAGS".to_string(),
+            });
+        }
+        if let Some(rustflags) = var("RUSTFLAGS") {
+            return Ok(Self {
+                flags: split_flags(&rustflags),
+                source: "RUSTFLAGS".to_string(),
+            });
+        }
+
+        let configs = read_configs(&config_files(cwd, var))?;
+        let target_env = format!(
+            "CARGO_TARGET_{}_RUSTFLAGS",
+            target.to_uppercase().replace(['-', '.'], "_")
+        );
+        let mut target_flags =
+            collect_config_flags(&configs, &["target", target, "rustflags"], var(&target_env));
+        let mut sources = Vec::new();
+        if !target_flags.is_empty() {
+            sources.push(format!("target.{target}.rustflags"));
+        }
+        let cfg_keys = cfg_keys(&configs);
+        if !cfg_keys.is_empty() {
+            let cfg = cfg()?;
+            for key in cfg_keys {
+                let predicate = &key["cfg(".len()..key.len() - 1];
+                if !cfg_matches(predicate, &cfg)
+                    .map_err(|e| format!("Invalid [target.'{key}'] in cargo config: {e}"))?
+                {
+                    continue;
+                }
+                let flags = collect_config_flags(&configs, &["target", &key, "rustflags"], None);
+                if !flags.is_empty() {
+                    target_flags.extend(flags);
+                    sources.push(format!("target.'{key}'.rustflags"));
+                }
+            }
+        }
+        if !target_flags.is_empty() {
+            return Ok(Self {
+                flags: target_flags,
+                source: sources.join(", "),
+            });
+        }
+
+        let build_flags = collect_config_flags(
+            &configs,
+            &["build", "rustflags"],
+            var("CARGO_BUILD_RUSTFLAGS"),
+        );
+        let source = if build_flags.is_empty() {
+            "none".to_string()
+        } else {
+            "build.rustflags".to_string()
+        };
+        Ok(Self {
+            flags: build_flags,
+            source,
+        })
+    }
+}
+
+fn split_flags(flags: &str) -> Vec<String> {
+    flags.split_whitespace().map(str::to_string).collect()
+}
+
+// Cargo config files from lowest to highest precedence: $CARGO_HOME first,
+// then every `.cargo/` directory from the filesystem root down to `cwd`
+fn config_files(cwd: &Path, var: &dyn Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = cwd.ancestors().map(|dir| dir.join(".cargo")).collect();
+    if let Some(cargo_home) = cargo_home(var)
+        && !dirs.contains(&cargo_home)
+    {
+        dirs.push(cargo_home);
+    }
+
+    dirs.into_iter()
+        .rev()
+        .filter_map(|dir| {
+            ["config.toml", "config"]
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        })
+        .collect()
+}
+
+fn cargo_home(var: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    var("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+}
+
+fn read_configs(files: &[PathBuf]) -> Result<Vec<toml::Table>, Box<dyn Error>> {
+    files
+        .iter()
+        .map(|file| {
+            let content = fs::read_to_string(file)?;
+            content
+                .parse()
+                .map_err(|e| format!("Failed to parse {}: {e}", file.display()).into())
+        })
+        .collect()
+}
+
+// Arrays from several config files are joined, higher precedence last,
+// and the matching environment variable is merged in after them
+fn collect_config_flags(
+    configs: &[toml::Table],
+    key: &[&str],
+    env_value: Option<String>,
+) -> Vec<String> {
+    let mut flags = Vec::new();
+    for config in configs {
+        flags.extend(lookup_flags(config, key));
+    }
+    if let Some(value) = env_value {
+        flags.extend(split_flags(&value));
+    }
+    flags
+}
+
+// `[target.'cfg(...)']` tables of every config file, sorted like cargo does
+fn cfg_keys(configs: &[toml::Table]) -> Vec<String> {
+    let mut keys: Vec<String> = configs
+        .iter()
+        .filter_map(|config| config.get("target")?.as_table())
+        .flat_map(|targets| targets.keys())
+        .filter(|key| key.starts_with("cfg(") && key.ends_with(')'))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// `name` or `name="value"`, as printed by `rustc --print cfg`
+type Cfg = (String, Option<String>);
+
+fn target_cfg(target: &str, toolchain: &str) -> Result<Vec<Cfg>, Box<dyn Error>> {
+    let output = Command::new("rustc")
+        .args([toolchain, "--print", "cfg", "--target", target])
+        .output()
+        .map_err(|e| format!("Failed to run rustc --print cfg: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustc --print cfg --target {target} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.trim_matches('"').to_string())),
+            None => (line.to_string(), None),
+        })
+        .collect())
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Equals,
+}
+
+fn cfg_tokens(predicate: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = predicate.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '=' => tokens.push(Token::Equals),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_whitespace() => {}
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(format!("unexpected `{c}`")),
+        }
+    }
+    Ok(tokens)
+}
+
+// Evaluates the inside of `cfg(...)`: `all(..)`, `any(..)`, `not(..)`,
+// `name` and `name = "value"`
+fn cfg_matches(predicate: &str, cfg: &[Cfg]) -> Result<bool, String> {
+    let tokens = cfg_tokens(predicate)?;
+    let mut pos = 0;
+    let matches = eval_cfg(&tokens, &mut pos, cfg)?;
+    if pos != tokens.len() {
+        return Err(format!("unexpected tokens after `{predicate}`"));
+    }
+    Ok(matches)
+}
+
+fn eval_cfg(tokens: &[Token], pos: &mut usize, cfg: &[Cfg]) -> Result<bool, String> {
+    let Some(Token::Ident(name)) = tokens.get(*pos) else {
+        return Err("expected a cfg name".to_string());
+    };
+    *pos += 1;
+    match (name.as_str(), tokens.get(*pos)) {
+        (operator @ ("all" | "any" | "not"), Some(Token::Open)) => {
+            *pos += 1;
+            let mut values = Vec::new();
+            while tokens.get(*pos) != Some(&Token::Close) {
+                values.push(eval_cfg(tokens, pos, cfg)?);
+                match tokens.get(*pos) {
+                    Some(Token::Comma) => *pos += 1,
+                    Some(Token::Close) => {}
+                    _ => return Err(format!("expected `,` or `)` in {operator}(..)")),
+                }
+            }
+            *pos += 1;
+            match operator {
+                "all" => Ok(values.iter().all(|&v| v)),
+                "any" => Ok(values.iter().any(|&v| v)),
+                _ => match values[..] {
+                    [value] => Ok(!value),
+                    _ => Err("not(..) takes exactly one predicate".to_string()),
+                },
+            }
+        }
+        (_, Some(Token::Equals)) => {
+            let Some(Token::Str(value)) = tokens.get(*pos + 1) else {
+                return Err(format!("expected a string after `{name} =`"));
+            };
+            *pos += 2;
+            Ok(cfg.contains(&(name.clone(), Some(value.clone()))))
+        }
+        _ => Ok(cfg.contains(&(name.clone(), None))),
+    }
+}
+
+fn lookup_flags(config: &toml::Table, key: &[&str]) -> Vec<String> {
+    let Some((last, parents)) = key.split_last() else {
+        return Vec::new();
+    };
+    let mut table = config;
+    for part in parents {
+        match table.get(*part).and_then(toml::Value::as_table) {
+            Some(next) => table = next,
+            None => return Vec::new(),
+        }
+    }
+    match table.get(*last) {
+        Some(toml::Value::String(flags)) => split_flags(flags),
+        Some(toml::Value::Array(flags)) => flags
+            .iter()
+            .filter_map(|flag| flag.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Encodes the final flag list for CARGO_ENCODED_RUSTFLAGS, which keeps
+// arguments containing spaces intact
+pub fn encode(flags: &[String]) -> String {
+    flags.join("\x1f")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_accepts_strings_and_arrays() {
+        let config: toml::Table = r#"
+            [build]
+            rustflags = "-C target-cpu=native  -C link-arg=-s"
+
+            [target.x86_64-unknown-linux-gnu]
+            rustflags = ["-C", "link-arg=-fuse-ld=mold"]
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            lookup_flags(&config, &["build", "rustflags"]),
+            ["-C", "target-cpu=native", "-C", "link-arg=-s"]
+        );
+        assert_eq!(
+            lookup_flags(
+                &config,
+                &["target", "x86_64-unknown-linux-gnu", "rustflags"]
+            ),
+            ["-C", "link-arg=-fuse-ld=mold"]
+        );
+        assert!(
+            lookup_flags(
+                &config,
+                &["target", "aarch64-unknown-linux-gnu", "rustflags"]
+            )
+            .is_empty()
+        );
+    }
+
+    fn linux_cfg() -> Vec<Cfg> {
+        vec![
+            ("unix".to_string(), None),
+            ("target_os".to_string(), Some("linux".to_string())),
+            ("target_arch".to_string(), Some("x86_64".to_string())),
+        ]
+    }
+
+    #[test]
+    fn cfg_predicates_evaluate_like_cargo() {
+        let cfg = linux_cfg();
+        assert_eq!(cfg_matches("unix", &cfg), Ok(true));
+        assert_eq!(cfg_matches("windows", &cfg), Ok(false));
+        assert_eq!(
+            cfg_matches(
+                r#"all(target_os = "linux", not(target_arch = "aarch64"))"#,
+                &cfg
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            cfg_matches(r#"any(windows, target_os = "macos",)"#, &cfg),
+            Ok(false)
+        );
+        assert!(cfg_matches("not(unix, windows)", &cfg).is_err());
+        assert!(cfg_matches("target_os = linux", &cfg).is_err());
+    }
+
+    #[test]
+    fn config_files_and_variables_follow_cargo_precedence() {
+        let dir = std::env::temp_dir().join(format!("rbt-rustflags-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".cargo")).unwrap();
+        fs::write(
+            dir.join(".cargo/config.toml"),
+            r#"
+[build]
+rustflags = ["-C", "opt-level=1"]
+
+[target.'cfg(unix)']
+rustflags = ["-C", "link-arg=-Wl,-z,now"]
+
+[target.'cfg(windows)']
+rustflags = ["-C", "target-feature=+crt-static"]
+
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-C", "target-cpu=native"]
+"#,
+        )
+        .unwrap();
+        let resolve = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .chain([(
+                    "CARGO_HOME".to_string(),
+                    dir.join(".cargo").display().to_string(),
+                )])
+                .collect();
+            UserRustflags::resolve_in(
+                &dir,
+                &|key| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()),
+                "x86_64-unknown-linux-gnu",
+                &|| Ok(linux_cfg()),
+            )
+            .unwrap()
+        };
+
+        let flags = resolve(&[]);
+        assert_eq!(
+            flags.flags,
+            ["-C", "target-cpu=native", "-C", "link-arg=-Wl,-z,now"]
+        );
+        assert_eq!(
+            flags.source,
+            "target.x86_64-unknown-linux-gnu.rustflags, target.'cfg(unix)'.rustflags"
+        );
+
+        let flags = resolve(&[("CARGO_ENCODED_RUSTFLAGS", ""), ("RUSTFLAGS", "-g")]);
+        assert!(flags.flags.is_empty());
+        assert_eq!(flags.source, "CARGO_ENCODED_RUSTFLAGS");
+        assert_eq!(
+            resolve(&[("RUSTFLAGS", "-g  -Dwarnings")]).flags,
+            ["-g", "-Dwarnings"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
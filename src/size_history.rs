@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+pub const HISTORY_FILE: &str = "rbt-size-history.json";
+// Oldest entries are dropped beyond this
+const MAX_ENTRIES: usize = 500;
+
+// Artifact sizes of one build of one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub commit: Option<String>,
+    pub dirty: bool,
+    pub timestamp: u64,
+    pub target: String,
+    pub sizes: BTreeMap<String, u64>,
+}
+
+impl HistoryEntry {
+    pub fn label(&self) -> String {
+        match &self.commit {
+            Some(commit) => {
+                let short = &commit[..commit.len().min(8)];
+                if self.dirty {
+                    format!("{short}+dirty")
+                } else {
+                    short.to_string()
+                }
+            }
+            None => "the previous build".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SizeHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+}
+
+impl SizeHistory {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut history = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?
+        } else {
+            SizeHistory::default()
+        };
+        history.path = path.to_path_buf();
+        Ok(history)
+    }
+
+    // The entry to compare against: the requested baseline commit, otherwise
+    // the latest build of the target from another commit (or any build without git)
+    pub fn baseline(
+        &self,
+        target: &str,
+        commit: Option<&str>,
+        baseline: Option<&str>,
+    ) -> Option<&HistoryEntry> {
+        let mut candidates = self.entries.iter().rev().filter(|e| e.target == target);
+        match baseline {
+            Some(baseline) => {
+                candidates.find(|e| e.commit.as_deref().is_some_and(|c| c.starts_with(baseline)))
+            }
+            None => candidates.find(|e| commit.is_none() || e.commit.as_deref() != commit),
+        }
+    }
+
+    // A rebuild of the same commit replaces the earlier entry
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if entry.commit.is_some() {
+            self.entries
+                .retain(|e| !(e.target == entry.target && e.commit == entry.commit));
+        }
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|e| format!("Failed to write {}: {e}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+// HEAD of the repository containing `dir` and whether the worktree is dirty
+pub fn git_commit(dir: &Path) -> Option<(String, bool)> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let dirty = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .is_ok_and(|o| !o.stdout.is_empty());
+    Some((commit, dirty))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// `--max-size` / `--max-growth` thresholds, checked per artifact
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SizeLimits {
+    pub max_size: Option<u64>,
+    pub max_growth: Option<f64>,
+}
+
+impl SizeLimits {
+    pub fn violations(
+        &self,
+        target: &str,
+        sizes: &BTreeMap<String, u64>,
+        baseline: Option<&HistoryEntry>,
+    ) -> Vec<String> {
+        let mut violations = Vec::new();
+        for (name, &size) in sizes {
+            if let Some(max_size) = self.max_size
+                && size > max_size
+            {
+                violations.push(format!(
+                    "{name} ({target}) is {size} bytes, above the {max_size} byte limit"
+                ));
+            }
+            let previous = baseline.and_then(|b| b.sizes.get(name));
+            if let (Some(max_growth), Some(&previous)) = (self.max_growth, previous)
+                && previous > 0
+            {
+                let growth = (size as f64 - previous as f64) * 100.0 / previous as f64;
+                if growth > max_growth {
+                    violations.push(format!(
+                        "{name} ({target}) grew {growth:.1}% ({previous} -> {size} bytes), above the {max_growth}% limit"
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+// Byte counts with an optional K/M/G suffix (powers of 1024), e.g. `512K` or `2MiB`
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("Invalid size `{value}` (expected e.g. 800K or 2M)")),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size `{value}` (expected e.g. 800K or 2M)"))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+// `5%` or `5`
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%');
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|p| *p >= 0.0)
+        .ok_or_else(|| format!("Invalid percentage `{value}` (expected e.g. 5%)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(commit: &str, target: &str, size: u64) -> HistoryEntry {
+        HistoryEntry {
+            commit: Some(commit.to_string()),
+            dirty: false,
+            timestamp: 0,
+            target: target.to_string(),
+            sizes: BTreeMap::from([("app".to_string(), size)]),
+        }
+    }
+
+    #[test]
+    fn baseline_skips_the_current_commit() {
+        let mut history = SizeHistory::default();
+        history.record(entry("aaaa", "x86_64", 100));
+        history.record(entry("bbbb", "aarch64", 90));
+        history.record(entry("cccc", "x86_64", 110));
+        history.record(entry("cccc", "x86_64", 120));
+        assert_eq!(history.entries.len(), 3);
+
+        let previous = history.baseline("x86_64", Some("cccc"), None).unwrap();
+        assert_eq!(previous.commit.as_deref(), Some("aaaa"));
+        let pinned = history
+            .baseline("x86_64", Some("dddd"), Some("cc"))
+            .unwrap();
+        assert_eq!(pinned.sizes["app"], 120);
+    }
+
+    #[test]
+    fn limits_report_size_and_growth() {
+        let baseline = entry("aaaa", "x86_64", 1000);
+        let limits = SizeLimits {
+            max_size: Some(1050),
+            max_growth: Some(5.0),
+        };
+        let sizes = BTreeMap::from([("app".to_string(), 1049)]);
+        assert!(
+            limits
+                .violations("x86_64", &sizes, Some(&baseline))
+                .is_empty()
+        );
+        let sizes = BTreeMap::from([("app".to_string(), 1060)]);
+        assert_eq!(
+            limits.violations("x86_64", &sizes, Some(&baseline)).len(),
+            2
+        );
+        assert_eq!(limits.violations("x86_64", &sizes, None).len(), 1);
+    }
+
+    #[test]
+    fn sizes_and_percentages_parse() {
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("1.5M"), Ok(1536 * 1024));
+        assert_eq!(parse_size("2048"), Ok(2048));
+        assert!(parse_size("12 parsecs").is_err());
+        assert_eq!(parse_percent("5%"), Ok(5.0));
+        assert!(parse_percent("-1").is_err());
+    }
+}
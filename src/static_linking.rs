@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardening;
+
+pub const CRT_STATIC_FLAGS: [&str; 2] = ["-C", "target-feature=+crt-static"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StaticLinking {
+    Musl,      // switch `*-linux-gnu*` targets to their musl counterpart
+    CrtStatic, // keep the target, link glibc statically
+}
+
+impl std::str::FromStr for StaticLinking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "musl" => Ok(StaticLinking::Musl),
+            "crt-static" => Ok(StaticLinking::CrtStatic),
+            _ => Err(format!(
+                "Unknown static linking method `{s}` (expected musl or crt-static)"
+            )),
+        }
+    }
+}
+
+// The triple to build for a static executable; `toolchain` is e.g. `+nightly`
+pub fn static_target(
+    target: &str,
+    method: StaticLinking,
+    toolchain: &str,
+) -> Result<String, Box<dyn Error>> {
+    if !target.contains("-linux-") {
+        return Err(format!("Static builds need a Linux target, not `{target}`").into());
+    }
+    if method == StaticLinking::CrtStatic || target.contains("-linux-musl") {
+        return Ok(target.to_string());
+    }
+
+    let musl = target.replacen("-linux-gnu", "-linux-musl", 1);
+    let output = Command::new("rustc")
+        .args([toolchain, "--print", "target-list"])
+        .output()
+        .map_err(|e| format!("Failed to execute rustc: {e}"))?;
+    let known = String::from_utf8_lossy(&output.stdout);
+    if musl == target || !known.lines().any(|t| t == musl) {
+        return Err(format!(
+            "No musl target matches `{target}`; use `--static crt-static` to link glibc statically"
+        )
+        .into());
+    }
+    Ok(musl)
+}
+
+// Fails unless the executable has neither PT_INTERP nor DT_NEEDED entries
+pub fn verify(path: &Path) -> Result<(), Box<dyn Error>> {
+    check(path)?;
+    println!(
+        "{}: statically linked",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    Ok(())
+}
+
+// Fails when the executable has a PT_INTERP or DT_NEEDED entry
+pub fn check(path: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let hardening = hardening::inspect(path)?
+        .ok_or_else(|| format!("{name} is not an ELF file, cannot verify static linking"))?;
+
+    let mut problems = Vec::new();
+    if let Some(interpreter) = &hardening.interpreter {
+        problems.push(format!("requests the dynamic loader {interpreter}"));
+    }
+    if !hardening.shared_libraries.is_empty() {
+        problems.push(format!("needs {}", hardening.shared_libraries.join(", ")));
+    }
+    if !problems.is_empty() {
+        return Err(format!(
+            "{name} is not statically linked: it {}",
+            problems.join(" and ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_linux_targets_are_rejected() {
+        assert!(static_target("x86_64-pc-windows-msvc", StaticLinking::Musl, "+stable").is_err());
+        assert_eq!(
+            static_target(
+                "aarch64-unknown-linux-gnu",
+                StaticLinking::CrtStatic,
+                "+stable"
+            )
+            .unwrap(),
+            "aarch64-unknown-linux-gnu"
+        );
+        assert_eq!(
+            static_target("x86_64-unknown-linux-musl", StaticLinking::Musl, "+stable").unwrap(),
+            "x86_64-unknown-linux-musl"
+        );
+    }
+}
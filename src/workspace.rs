@@ -0,0 +1,359 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub struct WorkspaceMember {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub authors: Vec<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub manifest_path: PathBuf,
+    pub bin_targets: Vec<String>,
+    pub example_targets: Vec<String>,
+}
+
+pub struct Workspace {
+    pub root: PathBuf,
+    pub target_directory: PathBuf,
+    pub members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let output = Command::new("cargo")
+            .args(["metadata", "--format-version=1", "--no-deps"])
+            .output()
+            .map_err(|e| format!("Failed to execute cargo metadata: {e}"))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        Self::from_metadata(&metadata)
+    }
+
+    fn from_metadata(metadata: &serde_json::Value) -> Result<Self, Box<dyn Error>> {
+        let root = metadata["workspace_root"]
+            .as_str()
+            .ok_or("Failed to get workspace root")?;
+        let target_directory = metadata["target_directory"]
+            .as_str()
+            .ok_or("Failed to get target directory")?;
+        let member_ids: Vec<&str> = metadata["workspace_members"]
+            .as_array()
+            .ok_or("Failed to get workspace members")?
+            .iter()
+            .filter_map(|id| id.as_str())
+            .collect();
+
+        let mut members = Vec::new();
+        for package in metadata["packages"].as_array().into_iter().flatten() {
+            if !member_ids.contains(&package["id"].as_str().unwrap_or_default()) {
+                continue;
+            }
+            let name = package["name"]
+                .as_str()
+                .ok_or("Package without a name in cargo metadata")?;
+            let manifest_path = package["manifest_path"]
+                .as_str()
+                .ok_or("Package without a manifest path in cargo metadata")?;
+            members.push(WorkspaceMember {
+                name: name.to_string(),
+                version: package["version"].as_str().unwrap_or("0.0.0").to_string(),
+                description: string_field(package, "description"),
+                license: string_field(package, "license"),
+                authors: package["authors"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|author| author.as_str().map(str::to_string))
+                    .collect(),
+                homepage: string_field(package, "homepage"),
+                repository: string_field(package, "repository"),
+                manifest_path: PathBuf::from(manifest_path),
+                bin_targets: targets_of_kind(package, "bin"),
+                example_targets: targets_of_kind(package, "example"),
+            });
+        }
+
+        Ok(Self {
+            root: PathBuf::from(root),
+            target_directory: PathBuf::from(target_directory),
+            members,
+        })
+    }
+
+    pub fn root_manifest(&self) -> PathBuf {
+        self.root.join("Cargo.toml")
+    }
+
+    // Explicitly requested packages, the members owning the requested
+    // `--bin`/`--example` targets, or every member that produces a binary
+    pub fn select_binaries(
+        &self,
+        packages: &[String],
+        bins: &[String],
+        examples: &[String],
+    ) -> Result<Vec<&WorkspaceMember>, Box<dyn Error>> {
+        let selected = self.select(packages)?;
+        for bin in bins {
+            if !selected.iter().any(|m| m.bin_targets.contains(bin)) {
+                return Err(
+                    format!("No binary target named `{bin}` in the selected packages").into(),
+                );
+            }
+        }
+        for example in examples {
+            if !selected.iter().any(|m| m.example_targets.contains(example)) {
+                return Err(format!(
+                    "No example target named `{example}` in the selected packages"
+                )
+                .into());
+            }
+        }
+
+        if !packages.is_empty() {
+            return Ok(selected);
+        }
+        if !bins.is_empty() || !examples.is_empty() {
+            return Ok(selected
+                .into_iter()
+                .filter(|m| {
+                    bins.iter().any(|b| m.bin_targets.contains(b))
+                        || examples.iter().any(|e| m.example_targets.contains(e))
+                })
+                .collect());
+        }
+
+        let binaries: Vec<_> = selected
+            .into_iter()
+            .filter(|m| !m.bin_targets.is_empty())
+            .collect();
+        if binaries.is_empty() {
+            return Err("No binary packages found in the workspace".into());
+        }
+        Ok(binaries)
+    }
+
+    // Explicitly requested packages, or the whole workspace
+    pub fn select(&self, packages: &[String]) -> Result<Vec<&WorkspaceMember>, Box<dyn Error>> {
+        if packages.is_empty() {
+            return Ok(self.members.iter().collect());
+        }
+
+        packages
+            .iter()
+            .map(|name| {
+                self.members
+                    .iter()
+                    .find(|m| &m.name == name)
+                    .ok_or_else(|| {
+                        format!("Package `{name}` is not a member of the workspace").into()
+                    })
+            })
+            .collect()
+    }
+}
+
+fn string_field(package: &serde_json::Value, key: &str) -> Option<String> {
+    package[key].as_str().map(str::to_string)
+}
+
+fn targets_of_kind(package: &serde_json::Value, kind: &str) -> Vec<String> {
+    package["targets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|target| {
+            target["kind"]
+                .as_array()
+                .is_some_and(|kinds| kinds.iter().any(|k| k == kind))
+        })
+        .filter_map(|target| target["name"].as_str().map(str::to_string))
+        .collect()
+}
+
+// Outcome of a quality check run against a single workspace member
+pub struct MemberCheck {
+    pub name: String,
+    pub passed: bool,
+}
+
+pub fn summarize_checks(check: &str, results: &[MemberCheck]) -> Result<(), Box<dyn Error>> {
+    if results.len() > 1 {
+        println!("\n{check} results:");
+        for result in results {
+            let status = if result.passed { "passed" } else { "FAILED" };
+            println!("  {:<30} {status}", result.name);
+        }
+    }
+
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| !r.passed)
+        .map(|r| r.name.as_str())
+        .collect();
+    if !failed.is_empty() {
+        return Err(format!("{check} checks failed for: {}", failed.join(", ")).into());
+    }
+    println!("{check} checks passed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `cargo metadata` of a virtual manifest with a library and an application
+    // member, plus a registry package that is not a member
+    fn workspace() -> Workspace {
+        let metadata = serde_json::json!({
+            "workspace_root": "/ws",
+            "target_directory": "/ws/target",
+            "workspace_members": [
+                "path+file:///ws/core#0.1.0",
+                "path+file:///ws/app#1.2.0"
+            ],
+            "packages": [
+                {
+                    "id": "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0",
+                    "name": "serde",
+                    "version": "1.0.0",
+                    "manifest_path": "/registry/serde/Cargo.toml",
+                    "targets": [{"name": "serde", "kind": ["lib"]}]
+                },
+                {
+                    "id": "path+file:///ws/core#0.1.0",
+                    "name": "core-lib",
+                    "version": "0.1.0",
+                    "manifest_path": "/ws/core/Cargo.toml",
+                    "targets": [{"name": "core_lib", "kind": ["lib"]}]
+                },
+                {
+                    "id": "path+file:///ws/app#1.2.0",
+                    "name": "app",
+                    "version": "1.2.0",
+                    "license": "MIT",
+                    "authors": ["Jane Doe <jane@example.com>"],
+                    "manifest_path": "/ws/app/Cargo.toml",
+                    "targets": [
+                        {"name": "app", "kind": ["bin"]},
+                        {"name": "app-tool", "kind": ["bin"]},
+                        {"name": "demo", "kind": ["example"]},
+                        {"name": "build-script-build", "kind": ["custom-build"]}
+                    ]
+                }
+            ]
+        });
+        Workspace::from_metadata(&metadata).unwrap()
+    }
+
+    fn names(members: &[&WorkspaceMember]) -> Vec<String> {
+        members.iter().map(|m| m.name.clone()).collect()
+    }
+
+    #[test]
+    fn virtual_manifest_members_come_from_metadata() {
+        let workspace = workspace();
+        assert_eq!(workspace.root_manifest(), PathBuf::from("/ws/Cargo.toml"));
+        assert_eq!(workspace.target_directory, PathBuf::from("/ws/target"));
+        assert_eq!(names(&workspace.select(&[]).unwrap()), ["core-lib", "app"]);
+
+        let app = &workspace.members[1];
+        assert_eq!(app.version, "1.2.0");
+        assert_eq!(app.license.as_deref(), Some("MIT"));
+        assert_eq!(app.authors, ["Jane Doe <jane@example.com>"]);
+        assert_eq!(app.bin_targets, ["app", "app-tool"]);
+        assert_eq!(app.example_targets, ["demo"]);
+        assert!(workspace.members[0].bin_targets.is_empty());
+    }
+
+    #[test]
+    fn package_selection_filters_and_rejects_unknown_members() {
+        let workspace = workspace();
+        let selected = workspace.select(&["core-lib".to_string()]).unwrap();
+        assert_eq!(names(&selected), ["core-lib"]);
+        assert_eq!(
+            workspace
+                .select(&["serde".to_string()])
+                .err()
+                .unwrap()
+                .to_string(),
+            "Package `serde` is not a member of the workspace"
+        );
+    }
+
+    #[test]
+    fn binaries_are_selected_by_package_bin_or_example() {
+        let workspace = workspace();
+        // Library-only members are skipped unless requested explicitly
+        assert_eq!(
+            names(&workspace.select_binaries(&[], &[], &[]).unwrap()),
+            ["app"]
+        );
+        assert_eq!(
+            names(
+                &workspace
+                    .select_binaries(&["core-lib".to_string()], &[], &[])
+                    .unwrap()
+            ),
+            ["core-lib"]
+        );
+        assert_eq!(
+            names(
+                &workspace
+                    .select_binaries(&[], &["app-tool".to_string()], &[])
+                    .unwrap()
+            ),
+            ["app"]
+        );
+        assert_eq!(
+            names(
+                &workspace
+                    .select_binaries(&[], &[], &["demo".to_string()])
+                    .unwrap()
+            ),
+            ["app"]
+        );
+
+        // `demo` is an example, not a binary
+        assert_eq!(
+            workspace
+                .select_binaries(&[], &["demo".to_string()], &[])
+                .err()
+                .unwrap()
+                .to_string(),
+            "No binary target named `demo` in the selected packages"
+        );
+        assert_eq!(
+            workspace
+                .select_binaries(&["core-lib".to_string()], &[], &["demo".to_string()])
+                .err()
+                .unwrap()
+                .to_string(),
+            "No example target named `demo` in the selected packages"
+        );
+    }
+
+    #[test]
+    fn workspaces_without_binaries_are_an_error() {
+        let mut workspace = workspace();
+        workspace.members.pop();
+        assert_eq!(
+            workspace
+                .select_binaries(&[], &[], &[])
+                .err()
+                .unwrap()
+                .to_string(),
+            "No binary packages found in the workspace"
+        );
+    }
+}